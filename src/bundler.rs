@@ -0,0 +1,147 @@
+use crate::dependency_scanner::{
+    is_c_like, is_rust, local_include, local_module, resolve_reference,
+};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Expands every local include (C/C++) or out-of-line module (Rust) of
+/// `src_path` into a single self-contained source.
+pub fn bundle_source(src_path: &Path) -> io::Result<String> {
+    let src_path = fs::canonicalize(src_path)?;
+    let mut bundled = String::new();
+
+    if is_c_like(&src_path) {
+        let mut included = HashSet::from([src_path.clone()]);
+        bundle_c_like(&src_path, &mut included, &mut bundled, false)?;
+    } else if is_rust(&src_path) {
+        bundle_rust(&src_path, &mut bundled, true)?;
+    } else {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Bundling {src_path:?} is not supported, only C/C++ and Rust are."),
+        ));
+    }
+
+    Ok(bundled)
+}
+
+fn read_lossy(path: &Path) -> io::Result<String> {
+    Ok(String::from_utf8_lossy(&fs::read(path)?).into_owned())
+}
+
+fn bundle_c_like(
+    path: &Path,
+    included: &mut HashSet<PathBuf>,
+    bundled: &mut String,
+    is_header: bool,
+) -> io::Result<()> {
+    let content = read_lossy(path)?;
+    let mut lines: Vec<&str> = content.lines().collect();
+
+    if is_header {
+        strip_include_guard(&mut lines);
+    }
+
+    for line in lines {
+        if is_pragma_once(line) {
+            continue;
+        }
+
+        if local_include(line).is_some() {
+            if let Some(header) = resolve_reference(path, line, false) {
+                // Every header is inlined once, standing in for its include guard.
+                if included.insert(header.clone()) {
+                    bundled.push_str(&format!("// begin {}\n", header.display()));
+                    bundle_c_like(&header, included, bundled, true)?;
+                    bundled.push_str(&format!("// end {}\n", header.display()));
+                }
+                continue;
+            }
+        }
+
+        bundled.push_str(line);
+        bundled.push('\n');
+    }
+
+    Ok(())
+}
+
+fn is_pragma_once(line: &str) -> bool {
+    line.trim_start()
+        .strip_prefix('#')
+        .map(|directive| directive.split_whitespace().collect::<Vec<_>>() == ["pragma", "once"])
+        .unwrap_or(false)
+}
+
+fn directive_argument<'a>(line: &'a str, directive: &str) -> Option<&'a str> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start();
+    let argument = rest.strip_prefix(directive)?;
+
+    argument
+        .starts_with(char::is_whitespace)
+        .then(|| argument.trim())
+}
+
+/// Removes a classic `#ifndef X` / `#define X` / `#endif` guard wrapping the
+/// whole header, leaving any other conditional untouched.
+fn strip_include_guard(lines: &mut Vec<&str>) {
+    let is_code = |line: &&str| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with("//")
+    };
+
+    let Some(ifndef_index) = lines.iter().position(is_code) else {
+        return;
+    };
+    let Some(define_index) = lines[ifndef_index + 1..]
+        .iter()
+        .position(is_code)
+        .map(|index| index + ifndef_index + 1)
+    else {
+        return;
+    };
+    let Some(endif_index) = lines.iter().rposition(is_code) else {
+        return;
+    };
+
+    let guard = directive_argument(lines[ifndef_index], "ifndef");
+    let defined = directive_argument(lines[define_index], "define");
+    let is_endif = lines[endif_index]
+        .trim_start()
+        .strip_prefix('#')
+        .is_some_and(|directive| directive.trim_start().starts_with("endif"));
+
+    if guard.is_some() && guard == defined && is_endif && endif_index > define_index {
+        lines.remove(endif_index);
+        lines.remove(define_index);
+        lines.remove(ifndef_index);
+    }
+}
+
+/// Inlined lines are kept as they are, as indenting them would change
+/// multiline string literals.
+fn bundle_rust(path: &Path, bundled: &mut String, is_root: bool) -> io::Result<()> {
+    let content = read_lossy(path)?;
+
+    for line in content.lines() {
+        if let (Some(module), Some(module_path)) =
+            (local_module(line), resolve_reference(path, line, is_root))
+        {
+            let declaration = line.trim_start();
+            let indent = &line[..line.len() - declaration.len()];
+            let visibility = &declaration[..declaration.find("mod ").unwrap_or_default()];
+
+            bundled.push_str(&format!("{indent}{visibility}mod {module} {{\n"));
+            bundle_rust(&module_path, bundled, false)?;
+            bundled.push_str(&format!("{indent}}}\n"));
+            continue;
+        }
+
+        bundled.push_str(line);
+        bundled.push('\n');
+    }
+
+    Ok(())
+}
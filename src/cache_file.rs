@@ -1,68 +1,395 @@
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::collections::hash_map::HashMap;
+use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use std::vec::Vec;
 
+use crate::utils::relative_path;
+
 pub const DEFUALT_BIN_DIR: &str = "binary";
 pub const DEFAULT_CACHE_FILE: &str = "erunner_cache.json";
 
 // Templates config for langueges compilation
+const TEMPLATE_CONFIG_BINARY: &str = "$(BINARY)";
 const TEMPLATE_CONFIG_BINARY_DIR: &str = "$(BIN_DIR)";
 const TEMPLATE_CONFIG_FILE: &str = "$(FILE)";
 const TEMPLATE_CONFIG_FILENAME: &str = "$(FILENAME)";
+const TEMPLATE_CONFIG_FILESTEM: &str = "$(FILESTEM)";
 const TEMPLATE_CONFIG_DIRNAME: &str = "$(DIR)";
 const TEMPLATE_CONFIG_DIR: &str = "$(DIRNAME)";
 const TEMPLATE_CONFIG_EXE_EXTENSION: &str = "$(EXE_EXT)";
 
+// Path every compile command must produce, and what is run by default.
+const DEFAULT_BINARY_TEMPLATE: &str = "$(BIN_DIR)/$(FILENAME).$(EXE_EXT)";
+const PROFILE_BINARY_TEMPLATE: &str = "$(BIN_DIR)/$(FILENAME).$(PROFILE).$(EXE_EXT)";
+const TEMPLATE_CONFIG_PROFILE: &str = "$(PROFILE)";
+pub const DEFAULT_PROFILE: &str = "default";
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Files {
     pub binary_dir_path: PathBuf,
     pub files: HashMap<String, FileCache>,
-    pub languages_config: HashMap<String, String>,
+    pub languages_config: HashMap<String, LanguageProfile>,
+    /// Runs solutions isolated, see `sandbox::spawn_isolated`.
+    #[serde(default)]
+    pub sandbox: bool,
+}
+
+/// How a source file is built and executed. `compile` is omitted for
+/// interpreted languages; `run` is the command spawned for every execution.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(from = "LanguageProfileConfig")]
+pub struct LanguageProfile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compile: Option<String>,
+    pub run: String,
+}
+
+// Older caches stored a single compile command per language.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum LanguageProfileConfig {
+    Command(String),
+    Profile {
+        #[serde(default)]
+        compile: Option<String>,
+        run: String,
+    },
+}
+
+impl From<LanguageProfileConfig> for LanguageProfile {
+    fn from(config: LanguageProfileConfig) -> Self {
+        match config {
+            LanguageProfileConfig::Command(compile) => LanguageProfile::compiled(&compile),
+            LanguageProfileConfig::Profile { compile, run } => LanguageProfile { compile, run },
+        }
+    }
+}
+
+impl LanguageProfile {
+    pub fn compiled(compile: &str) -> Self {
+        Self {
+            compile: Some(compile.to_string()),
+            run: TEMPLATE_CONFIG_BINARY.to_string(),
+        }
+    }
+
+    pub fn interpreted(run: &str) -> Self {
+        Self {
+            compile: None,
+            run: run.to_string(),
+        }
+    }
+
+    pub fn with_run(compile: &str, run: &str) -> Self {
+        Self {
+            compile: Some(compile.to_string()),
+            run: run.to_string(),
+        }
+    }
+}
+
+impl Files {
+    /// Looks up the language profile of `source_path`. Named profiles are
+    /// stored as `<extension>.<profile>`, e.g. `cpp.release`.
+    pub fn language_profile(
+        &self,
+        source_path: &Path,
+        profile: Option<&str>,
+    ) -> Result<&LanguageProfile, String> {
+        let file_type = source_path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .ok_or_else(|| "Failed to determine file type".to_string())?;
+
+        let Some(profile) = profile else {
+            return self
+                .languages_config
+                .get(file_type)
+                .ok_or_else(|| format!("File type \"{}\" is not supported.", file_type));
+        };
+
+        self.languages_config
+            .get(&format!("{file_type}.{profile}"))
+            .ok_or_else(|| {
+                format!(
+                    "Profile \"{}\" is not configured for file type \"{}\".",
+                    profile, file_type
+                )
+            })
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct FileCache {
     pub source_hash: String,
     pub tests: Vec<Test>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub builds: HashMap<String, BuildKey>,
+    /// Bytes a test run may print before it is stopped, the default when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_limit: Option<usize>,
+    /// A test run is stopped once it takes longer, none when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_limit: Option<Duration>,
+    /// Peak memory in bytes a test run may use, none when unset.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit: Option<usize>,
+    /// Set for problems using files instead of stdin and stdout.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_files: Option<IoFiles>,
+    /// Verdict each test and sub-test last ran with, keyed by selector, e.g. `3.2`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub last_verdicts: BTreeMap<String, Verdict>,
+}
+
+/// Names of the files a solution reads its input from and writes its output
+/// to, relative to the temporary directory each test runs in.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct IoFiles {
+    pub input: String,
+    pub output: String,
+}
+
+/// Command-line arguments and environment variables passed to a binary on
+/// top of its stdin.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunArgs {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Limits of a single test, overriding those of its file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time_limit: Option<Duration>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub memory_limit: Option<usize>,
+}
+
+impl RunArgs {
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+            && self.env.is_empty()
+            && self.time_limit.is_none()
+            && self.memory_limit.is_none()
+    }
+
+    /// Layers `overrides` on top: its arguments and limits replace these when
+    /// given, and its variables are added to these.
+    pub fn merged_with(&self, overrides: &RunArgs) -> RunArgs {
+        let mut env = self.env.clone();
+        env.extend(overrides.env.clone());
+
+        RunArgs {
+            args: if overrides.args.is_empty() {
+                self.args.clone()
+            } else {
+                overrides.args.clone()
+            },
+            env,
+            time_limit: overrides.time_limit.or(self.time_limit),
+            memory_limit: overrides.memory_limit.or(self.memory_limit),
+        }
+    }
+}
+
+impl Display for RunArgs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let env = self.env.iter().map(|(key, value)| format!("{key}={value}"));
+        let args = self.args.iter().cloned();
+
+        write!(f, "{}", shell_words::join(env.chain(args)))
+    }
+}
+
+/// Judgement of a single test run.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Passed,
+    WrongAnswer,
+    RuntimeError,
+    OutputLimitExceeded,
+    TimeLimitExceeded,
+    MemoryLimitExceeded,
+}
+
+impl Verdict {
+    pub fn is_passed(self) -> bool {
+        self == Verdict::Passed
+    }
+}
+
+impl std::fmt::Display for Verdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Verdict::Passed => write!(f, "Passed"),
+            Verdict::WrongAnswer => write!(f, "Wrong Answer"),
+            Verdict::RuntimeError => write!(f, "Runtime Error"),
+            Verdict::OutputLimitExceeded => write!(f, "Output Limit Exceeded"),
+            Verdict::TimeLimitExceeded => write!(f, "Time Limit Exceeded"),
+            Verdict::MemoryLimitExceeded => write!(f, "Memory Limit Exceeded"),
+        }
+    }
+}
+
+/// Name and tags used to select a test, or a block inside a test file,
+/// without relying on its position.
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TestLabels {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+impl TestLabels {
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.tags.is_empty()
+    }
+}
+
+/// Everything a binary depends on. The binary is reused only when all of it
+/// still matches the current source, compile command and toolchain.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct BuildKey {
+    pub source_hash: String,
+    #[serde(default)]
+    pub dependencies_hash: String,
+    pub command: String,
+    pub toolchain_hash: String,
+}
+
+impl BuildKey {
+    /// Lists what differs between the key a binary was built with and `current`.
+    pub fn stale_reasons(&self, current: &BuildKey) -> Vec<&'static str> {
+        let mut reasons = Vec::new();
+
+        if self.source_hash != current.source_hash {
+            reasons.push("source");
+        }
+        if self.dependencies_hash != current.dependencies_hash {
+            reasons.push("dependencies");
+        }
+        if self.command != current.command {
+            reasons.push("command");
+        }
+        if self.toolchain_hash != current.toolchain_hash {
+            reasons.push("toolchain");
+        }
+
+        reasons
+    }
+}
+
+impl FileCache {
+    pub fn new(source_hash: String, tests: Vec<Test>) -> Self {
+        Self {
+            source_hash,
+            tests,
+            builds: HashMap::new(),
+            output_limit: None,
+            time_limit: None,
+            memory_limit: None,
+            io_files: None,
+            last_verdicts: BTreeMap::new(),
+        }
+    }
+
+    /// Selectors of the tests and sub-tests that failed when they last ran.
+    pub fn failed_tests(&self) -> Vec<&str> {
+        self.last_verdicts
+            .iter()
+            .filter(|(_, verdict)| !verdict.is_passed())
+            .map(|(selector, _)| selector.as_str())
+            .collect()
+    }
+
+    /// Key the binary of `profile` was last built with, if it was ever built.
+    pub fn build_key(&self, profile: Option<&str>) -> Option<&BuildKey> {
+        self.builds.get(profile.unwrap_or(DEFAULT_PROFILE))
+    }
+
+    pub fn set_build_key(&mut self, profile: Option<&str>, build_key: BuildKey) {
+        if profile.is_none() {
+            self.source_hash = build_key.source_hash.clone();
+        }
+
+        self.builds
+            .insert(profile.unwrap_or(DEFAULT_PROFILE).to_string(), build_key);
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum Test {
     StringTest {
         input: String,
-        expected_output: String,
+        /// None when the test only checks the program runs fine.
+        expected_output: Option<String>,
+        #[serde(default, skip_serializing_if = "RunArgs::is_empty")]
+        run_args: RunArgs,
+        #[serde(default, skip_serializing_if = "TestLabels::is_empty")]
+        labels: TestLabels,
     },
     RefTest {
         input: PathBuf,
         expected_output: Option<PathBuf>,
+        #[serde(default, skip_serializing_if = "RunArgs::is_empty")]
+        run_args: RunArgs,
+        #[serde(default, skip_serializing_if = "TestLabels::is_empty")]
+        labels: TestLabels,
     },
 }
 
+impl Test {
+    pub fn labels(&self) -> &TestLabels {
+        match self {
+            Test::StringTest { labels, .. } | Test::RefTest { labels, .. } => labels,
+        }
+    }
+}
+
 impl Display for Test {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
+        let run_args = match self {
             Test::StringTest {
                 input,
                 expected_output,
+                run_args,
+                ..
             } => {
-                write!(f, "Test: ({}), Expected: ({})", input, expected_output)
+                match expected_output {
+                    Some(expected_output) => {
+                        write!(f, "Test: ({}), Expected: ({})", input, expected_output)?
+                    }
+                    None => write!(f, "Test: ({}), No expected output", input)?,
+                }
+                run_args
             }
             Test::RefTest {
                 input,
                 expected_output,
+                run_args,
+                ..
             } => {
                 write!(
                     f,
                     "Test from file: ({:?}), Expected from file: ({:?})",
                     input, expected_output
-                )
+                )?;
+                run_args
             }
+        };
+
+        if !run_args.is_empty() {
+            write!(f, ", Run with: ({})", run_args)?;
         }
+
+        Ok(())
     }
 }
 
@@ -70,7 +397,21 @@ pub fn template_config_replacement(
     config: &mut String,
     binary_dir_path: &Path,
     source_path: &Path,
+    profile: Option<&str>,
 ) -> Result<(), &'static str> {
+    // Replace TEMPLATE_CONFIG_BINARY first, it expands into other templates
+    *config = config.replace(
+        TEMPLATE_CONFIG_BINARY,
+        if profile.is_some() {
+            PROFILE_BINARY_TEMPLATE
+        } else {
+            DEFAULT_BINARY_TEMPLATE
+        },
+    );
+
+    // Replace TEMPLATE_CONFIG_PROFILE with the selected build profile name
+    *config = config.replace(TEMPLATE_CONFIG_PROFILE, profile.unwrap_or(DEFAULT_PROFILE));
+
     // Replace TEMPLATE_CONFIG_BINARY_DIR with binary directory path
     *config = config.replace(
         TEMPLATE_CONFIG_BINARY_DIR,
@@ -89,6 +430,16 @@ pub fn template_config_replacement(
             .ok_or("Invalid source file stem")?,
     );
 
+    // Replace TEMPLATE_CONFIG_FILESTEM with the filename without its extension
+    *config = config.replace(
+        TEMPLATE_CONFIG_FILESTEM,
+        source_path
+            .file_stem()
+            .ok_or("Invalid source file stem")?
+            .to_str()
+            .ok_or("Invalid source file stem")?,
+    );
+
     // Replace TEMPLATE_CONFIG_FILE with the full source file path
     *config = config.replace(
         TEMPLATE_CONFIG_FILE,
@@ -126,6 +477,63 @@ pub fn template_config_replacement(
     Ok(())
 }
 
+/// Returns the project root, the directory holding the cache file.
+pub fn project_root() -> io::Result<PathBuf> {
+    fs::canonicalize(".")
+}
+
+/// Splits `template` into arguments, expanding each so paths with spaces stay whole.
+pub fn expand_command(
+    template: &str,
+    binary_dir_path: &Path,
+    source_path: &Path,
+    profile: Option<&str>,
+) -> Result<Vec<String>, String> {
+    let mut arguments =
+        shell_words::split(template).map_err(|_| "Failed to parse command".to_string())?;
+
+    for argument in arguments.iter_mut() {
+        template_config_replacement(argument, binary_dir_path, source_path, profile)
+            .map_err(|err| format!("Template error: {}", err))?;
+    }
+
+    if arguments.is_empty() {
+        return Err("System call command is empty".to_string());
+    }
+
+    Ok(arguments)
+}
+
+/// Path of the binary produced by the compile command of `source_path`.
+pub fn binary_path(
+    binary_dir_path: &Path,
+    source_path: &Path,
+    profile: Option<&str>,
+) -> Result<PathBuf, &'static str> {
+    let mut binary_path = TEMPLATE_CONFIG_BINARY.to_string();
+    template_config_replacement(&mut binary_path, binary_dir_path, source_path, profile)?;
+    Ok(PathBuf::from(binary_path))
+}
+
+/// Converts an absolute path into one relative to the project root, so the
+/// cache stays valid when the project is moved or cloned elsewhere.
+pub fn to_project_path(path: &Path) -> io::Result<PathBuf> {
+    if !path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+
+    Ok(relative_path(path, &project_root()?).unwrap_or_else(|| path.to_path_buf()))
+}
+
+/// Resolves a path stored in the cache against the project root.
+pub fn resolve_project_path(path: &Path) -> io::Result<PathBuf> {
+    if path.is_absolute() {
+        return Ok(path.to_path_buf());
+    }
+
+    Ok(project_root()?.join(path))
+}
+
 pub fn get_file(filename: &str) -> io::Result<Option<FileCache>> {
     let cache_file_path = Path::new(".").join(DEFAULT_CACHE_FILE);
 
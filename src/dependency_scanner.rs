@@ -0,0 +1,169 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const C_LIKE_EXTENSIONS: [&str; 8] = ["c", "cc", "cpp", "cxx", "h", "hh", "hpp", "hxx"];
+const RUST_EXTENSION: &str = "rs";
+
+pub fn is_c_like(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| C_LIKE_EXTENSIONS.contains(&extension))
+}
+
+pub fn is_rust(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .is_some_and(|extension| extension == RUST_EXTENSION)
+}
+
+/// Returns the header named by a local `#include "..."` line. System
+/// includes (`<...>`) are never followed.
+pub fn local_include(line: &str) -> Option<&str> {
+    let directive = line.trim_start().strip_prefix('#')?.trim_start();
+    let header = directive.strip_prefix("include")?.trim_start();
+    let header = header.strip_prefix('"')?;
+
+    header.split_once('"').map(|(header, _)| header)
+}
+
+/// Returns the module named by an out-of-line `mod name;` declaration.
+pub fn local_module(line: &str) -> Option<&str> {
+    let mut declaration = line.trim_start();
+
+    if let Some(rest) = declaration.strip_prefix("pub") {
+        // Skip visibility such as `pub` or `pub(crate)`.
+        declaration = match rest.trim_start().strip_prefix('(') {
+            Some(rest) => rest.split_once(')')?.1,
+            None => rest,
+        };
+    }
+
+    let module = declaration.trim_start().strip_prefix("mod ")?.trim();
+    let module = module.strip_suffix(';')?.trim_end();
+
+    (!module.is_empty()
+        && module
+            .chars()
+            .all(|chr| chr.is_alphanumeric() || chr == '_'))
+    .then_some(module)
+}
+
+/// Resolves the local file referenced by `line` of `path`, if there is one.
+/// `is_root` tells whether `path` is the crate root, which only matters for
+/// Rust modules.
+pub fn resolve_reference(path: &Path, line: &str, is_root: bool) -> Option<PathBuf> {
+    let directory = path.parent()?;
+
+    let candidates = if is_c_like(path) {
+        vec![directory.join(local_include(line)?)]
+    } else if is_rust(path) {
+        let module = local_module(line)?;
+
+        // Modules declared in the crate root, `main.rs`, `lib.rs` or `mod.rs`
+        // live next to it, those declared in any other `foo.rs` in `foo/`.
+        let file_name = path.file_name()?.to_str()?;
+        let module_directory = if is_root || ["main.rs", "lib.rs", "mod.rs"].contains(&file_name) {
+            directory.to_path_buf()
+        } else {
+            directory.join(path.file_stem()?)
+        };
+
+        vec![
+            module_directory.join(format!("{module}.rs")),
+            module_directory.join(module).join("mod.rs"),
+        ]
+    } else {
+        return None;
+    };
+
+    candidates
+        .into_iter()
+        .find(|candidate| candidate.is_file())
+        .and_then(|candidate| fs::canonicalize(candidate).ok())
+}
+
+/// Collects every local file `src_path` depends on, following nested
+/// includes and modules. The source itself is not part of the result.
+pub fn scan_dependencies(src_path: &Path) -> io::Result<Vec<PathBuf>> {
+    let src_path = fs::canonicalize(src_path)?;
+    let mut dependencies: BTreeSet<PathBuf> = BTreeSet::new();
+    let mut pending = vec![src_path.clone()];
+
+    while let Some(path) = pending.pop() {
+        for line in String::from_utf8_lossy(&fs::read(&path)?).lines() {
+            if let Some(dependency) = resolve_reference(&path, line, path == src_path) {
+                if dependency != src_path && dependencies.insert(dependency.clone()) {
+                    pending.push(dependency);
+                }
+            }
+        }
+    }
+
+    Ok(dependencies.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of source files, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str, files: &[(&str, &str)]) -> Self {
+            let root = std::env::temp_dir().join(format!("erunner-{}-{name}", std::process::id()));
+            let _ = fs::remove_dir_all(&root);
+
+            for (path, contents) in files {
+                let path = root.join(path);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(path, contents).unwrap();
+            }
+            Self(fs::canonicalize(root).unwrap())
+        }
+
+        fn resolve(&self, path: &str, is_root: bool) -> Option<PathBuf> {
+            resolve_reference(&self.0.join(path), "mod bar;", is_root)
+                .map(|dependency| dependency.strip_prefix(&self.0).unwrap().to_path_buf())
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn modules_of_non_root_files_live_in_their_directory() {
+        let project = TempDir::new(
+            "modules",
+            &[
+                ("main.rs", ""),
+                ("solution.rs", ""),
+                ("foo.rs", ""),
+                ("bar.rs", ""),
+                ("foo/bar.rs", ""),
+                ("nested/mod.rs", ""),
+                ("nested/bar/mod.rs", ""),
+                ("other.rs", ""),
+                ("other/bar/mod.rs", ""),
+            ],
+        );
+
+        assert_eq!(project.resolve("main.rs", false), Some("bar.rs".into()));
+        assert_eq!(project.resolve("solution.rs", true), Some("bar.rs".into()));
+        assert_eq!(project.resolve("foo.rs", false), Some("foo/bar.rs".into()));
+        assert_eq!(
+            project.resolve("nested/mod.rs", false),
+            Some("nested/bar/mod.rs".into())
+        );
+        assert_eq!(
+            project.resolve("other.rs", false),
+            Some("other/bar/mod.rs".into())
+        );
+        assert_eq!(project.resolve("solution.rs", false), None);
+    }
+}
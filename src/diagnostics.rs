@@ -0,0 +1,263 @@
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use std::path::PathBuf;
+
+// Condensed output stops after this many diagnostics.
+const MAX_DISPLAYED_DIAGNOSTICS: usize = 20;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+    Help,
+}
+
+impl Severity {
+    fn from_str(string: &str) -> Option<Self> {
+        match string.trim() {
+            "error" | "fatal error" => Some(Self::Error),
+            "warning" => Some(Self::Warning),
+            "note" => Some(Self::Note),
+            "help" => Some(Self::Help),
+            // rustc attaches lint codes, e.g. `error[E0425]`.
+            severity => severity
+                .split_once('[')
+                .and_then(|(severity, _)| Self::from_str(severity)),
+        }
+    }
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "{}", "error".red().bold()),
+            Severity::Warning => write!(f, "{}", "warning".yellow().bold()),
+            Severity::Note => write!(f, "{}", "note".cyan()),
+            Severity::Help => write!(f, "{}", "help".cyan()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: u32,
+    pub column: Option<u32>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let location = match self.column {
+            Some(column) => format!("{}:{}:{}", self.file.display(), self.line, column),
+            None => format!("{}:{}", self.file.display(), self.line),
+        };
+
+        write!(f, "{} {}: {}", location.bold(), self.severity, self.message)
+    }
+}
+
+/// Written next to the binary after every compilation so editors can jump
+/// to the reported locations.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct CompilationReport {
+    pub source: PathBuf,
+    pub success: bool,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl CompilationReport {
+    pub fn error_count(&self) -> usize {
+        self.diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn first_error(&self) -> Option<&Diagnostic> {
+        self.diagnostics
+            .iter()
+            .find(|diagnostic| diagnostic.severity == Severity::Error)
+    }
+}
+
+/// Parses `file:line[:column]` from the right, so drive letters survive.
+fn parse_location(location: &str) -> Option<(PathBuf, u32, Option<u32>)> {
+    let mut parts = location.trim().rsplitn(3, ':');
+    let last = parts.next()?.parse::<u32>().ok()?;
+
+    let middle = parts.next()?;
+    match (middle.parse::<u32>(), parts.next()) {
+        (Ok(line), Some(file)) if !file.is_empty() => Some((PathBuf::from(file), line, Some(last))),
+        _ => {
+            let file = location.trim().rsplit_once(':')?.0;
+            (!file.is_empty()).then(|| (PathBuf::from(file), last, None))
+        }
+    }
+}
+
+/// `file:line:column: severity: message`, as printed by gcc and clang.
+fn parse_gcc_line(line: &str) -> Option<Diagnostic> {
+    for separator in [": fatal error: ", ": error: ", ": warning: ", ": note: "] {
+        if let Some((location, message)) = line.split_once(separator) {
+            let (file, line, column) = parse_location(location)?;
+
+            return Some(Diagnostic {
+                file,
+                line,
+                column,
+                severity: Severity::from_str(separator.trim_matches([':', ' ']))?,
+                message: message.trim().to_string(),
+            });
+        }
+    }
+
+    None
+}
+
+/// Extracts the diagnostics of gcc, clang and rustc from compiler output.
+/// rustc prints `severity: message` with the location on a later `-->` line.
+pub fn parse_diagnostics(output: &str) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let mut pending: Option<(Severity, String)> = None;
+
+    for line in output.lines() {
+        if let Some(diagnostic) = parse_gcc_line(line) {
+            pending = None;
+            diagnostics.push(diagnostic);
+            continue;
+        }
+
+        if let Some(location) = line.trim_start().strip_prefix("--> ") {
+            if let (Some((severity, message)), Some((file, line, column))) =
+                (pending.take(), parse_location(location))
+            {
+                diagnostics.push(Diagnostic {
+                    file,
+                    line,
+                    column,
+                    severity,
+                    message,
+                });
+            }
+            continue;
+        }
+
+        if !line.starts_with(char::is_whitespace) {
+            pending = line.split_once(": ").and_then(|(severity, message)| {
+                Severity::from_str(severity).map(|severity| (severity, message.trim().to_string()))
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// Prints errors and warnings one per line, with their notes indented below.
+pub fn print_diagnostics(diagnostics: &[Diagnostic]) {
+    for diagnostic in diagnostics.iter().take(MAX_DISPLAYED_DIAGNOSTICS) {
+        match diagnostic.severity {
+            Severity::Error | Severity::Warning => println!("  {diagnostic}"),
+            Severity::Note | Severity::Help => println!("    {diagnostic}"),
+        }
+    }
+
+    if diagnostics.len() > MAX_DISPLAYED_DIAGNOSTICS {
+        println!(
+            "  {}",
+            format!(
+                "... {} more diagnostics omitted.",
+                diagnostics.len() - MAX_DISPLAYED_DIAGNOSTICS
+            )
+            .italic()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Location<'a> = (&'a str, u32, Option<u32>, Severity);
+
+    fn locations(output: &str) -> Vec<(String, u32, Option<u32>, Severity)> {
+        parse_diagnostics(output)
+            .into_iter()
+            .map(|diagnostic| {
+                (
+                    diagnostic.file.display().to_string(),
+                    diagnostic.line,
+                    diagnostic.column,
+                    diagnostic.severity,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parse_compiler_output() {
+        let cases: &[(&str, &[Location])] = &[
+            (
+                "a.cpp: In function 'int main()':\n\
+                 a.cpp:3:9: warning: unused variable 'x' [-Wunused-variable]\n    \
+                 3 |     int x;\n      |         ^\n",
+                &[("a.cpp", 3, Some(9), Severity::Warning)],
+            ),
+            (
+                "a.cpp:4:5: error: 'foo' was not declared in this scope\n    \
+                 4 |     foo();\n      |     ^~~\n\
+                 a.cpp:2:6: note: 'bar' declared here\n",
+                &[
+                    ("a.cpp", 4, Some(5), Severity::Error),
+                    ("a.cpp", 2, Some(6), Severity::Note),
+                ],
+            ),
+            (
+                "error[E0425]: cannot find value `x` in this scope\n \
+                 --> src/main.rs:2:20\n  |\n\
+                 2 |     println!(\"{}\", x);\n  |                    ^ not found in this scope\n\n\
+                 warning: unused variable: `y`\n \
+                 --> src/main.rs:3:9\n\n\
+                 error: aborting due to 1 previous error\n",
+                &[
+                    ("src/main.rs", 2, Some(20), Severity::Error),
+                    ("src/main.rs", 3, Some(9), Severity::Warning),
+                ],
+            ),
+            (
+                "/usr/bin/ld: /tmp/ccX.o: in function `main':\n\
+                 a.cpp:(.text+0x9): undefined reference to `foo()'\n\
+                 collect2: error: ld returned 1 exit status\n",
+                &[],
+            ),
+        ];
+
+        for (output, expected) in cases {
+            let expected: Vec<_> = expected
+                .iter()
+                .map(|(file, line, column, severity)| (file.to_string(), *line, *column, *severity))
+                .collect();
+            assert_eq!(locations(output), expected, "{output}");
+        }
+    }
+
+    #[test]
+    fn first_error_skips_warnings() {
+        let output = "a.cpp:1:1: warning: w\na.cpp:5:2: error: e\n";
+        let report = CompilationReport {
+            source: PathBuf::from("a.cpp"),
+            success: false,
+            diagnostics: parse_diagnostics(output),
+        };
+
+        assert_eq!(report.error_count(), 1);
+        assert_eq!(
+            report.first_error().map(|diagnostic| diagnostic.line),
+            Some(5)
+        );
+    }
+}
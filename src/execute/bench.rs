@@ -0,0 +1,314 @@
+use super::core::ExecutionTarget;
+use super::test::{effective_output_limit, judge, selected_cases, TestCase, TestOutcome};
+use super::{cache_key, prepare_binary, RunError};
+use crate::cache_file::{get_config, Verdict};
+use crate::log;
+use crate::selector_evaluator::evaluate;
+
+use colored::{ColoredString, Colorize};
+use std::path::Path;
+use std::time::Duration;
+
+/// A source file built with a profile, one side of a comparison.
+struct Variant<'a> {
+    src_path: &'a Path,
+    profile: Option<&'a str>,
+    label: String,
+}
+
+impl<'a> Variant<'a> {
+    fn new(src_path: &'a Path, profile: Option<&'a str>) -> Result<Self, RunError> {
+        let filename = cache_key(src_path)?;
+
+        Ok(Self {
+            src_path,
+            profile,
+            label: match profile {
+                Some(profile) => format!("{filename} ({profile})"),
+                None => filename.to_string(),
+            },
+        })
+    }
+}
+
+/// Timings of the measured runs of a test, warmup runs excluded.
+struct Stats {
+    runs: usize,
+    min: Duration,
+    median: Duration,
+    mean: Duration,
+    stddev: Duration,
+    max: Duration,
+}
+
+impl Stats {
+    fn new(mut times: Vec<Duration>) -> Self {
+        times.sort();
+
+        let runs = times.len();
+        let middle = runs / 2;
+        let median = match runs % 2 {
+            0 => (times[middle - 1] + times[middle]) / 2,
+            _ => times[middle],
+        };
+
+        let mean = times.iter().map(Duration::as_secs_f64).sum::<f64>() / runs as f64;
+        // Sample deviation, the runs being a sample of every possible one.
+        let variance = match runs {
+            1 => 0.0,
+            _ => {
+                times
+                    .iter()
+                    .map(|time| (time.as_secs_f64() - mean).powi(2))
+                    .sum::<f64>()
+                    / (runs - 1) as f64
+            }
+        };
+
+        Self {
+            runs,
+            min: times[0],
+            median,
+            mean: Duration::from_secs_f64(mean),
+            stddev: Duration::from_secs_f64(variance.sqrt()),
+            max: times[runs - 1],
+        }
+    }
+
+    /// Squared standard error of the mean, in seconds.
+    fn squared_error(&self) -> f64 {
+        self.stddev.as_secs_f64().powi(2) / self.runs as f64
+    }
+}
+
+enum Measurement {
+    Timed(Stats),
+    /// A run didn't pass, so its timing means nothing.
+    Failed(Verdict),
+}
+
+/// How the mean of a variant compares to the baseline.
+enum Change {
+    Faster(f64),
+    Slower(f64),
+    WithinNoise,
+}
+
+impl Change {
+    /// Differences within two standard errors, about 95% confidence, are
+    /// considered noise.
+    fn new(baseline: &Stats, other: &Stats) -> Self {
+        let (baseline_mean, other_mean) = (baseline.mean.as_secs_f64(), other.mean.as_secs_f64());
+        let noise = 2.0 * (baseline.squared_error() + other.squared_error()).sqrt();
+
+        if (other_mean - baseline_mean).abs() <= noise || other_mean == 0.0 {
+            Change::WithinNoise
+        } else if other_mean < baseline_mean {
+            Change::Faster(baseline_mean / other_mean)
+        } else {
+            Change::Slower(other_mean / baseline_mean)
+        }
+    }
+
+    fn to_colored_string(&self) -> ColoredString {
+        match self {
+            Change::Faster(ratio) => format!("{ratio:.2}x faster").green(),
+            Change::Slower(ratio) => format!("{ratio:.2}x slower").red(),
+            Change::WithinNoise => "within noise".normal(),
+        }
+    }
+}
+
+/// Runs `case` `warmup` times unmeasured, then `runs` times.
+fn measure(
+    target: &ExecutionTarget,
+    case: &TestCase,
+    runs: usize,
+    warmup: usize,
+) -> Result<Measurement, RunError> {
+    let mut times = Vec::with_capacity(runs);
+
+    for run in 0..warmup + runs {
+        let (verdict, _, time_elapsed) = judge(
+            target,
+            case.input.clone(),
+            &case.run_args,
+            case.expected_output.as_ref(),
+        )?;
+
+        if !verdict.is_passed() {
+            return Ok(Measurement::Failed(verdict));
+        }
+        if run >= warmup {
+            times.push(time_elapsed);
+        }
+    }
+
+    Ok(Measurement::Timed(Stats::new(times)))
+}
+
+fn print_row(selector: &str, label: &str, measurement: &Measurement, change: ColoredString) {
+    let selector = match selector {
+        "" => String::new(),
+        selector => format!("#{selector}"),
+    };
+
+    match measurement {
+        Measurement::Timed(stats) => println!(
+            "{:<8} {:<20} {:>10} {:>10} {:>10} {:>10} {:>10}  {}",
+            selector.yellow(),
+            label,
+            format!("{:.1?}", stats.min),
+            format!("{:.1?}", stats.median).green(),
+            format!("{:.1?}", stats.mean),
+            format!("±{:.1?}", stats.stddev),
+            format!("{:.1?}", stats.max),
+            change
+        ),
+        Measurement::Failed(verdict) => println!(
+            "{:<8} {:<20} {}",
+            selector.yellow(),
+            label,
+            verdict.to_string().red()
+        ),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn bench(
+    src_path: &Path,
+    expression: Option<&str>,
+    profile: Option<&str>,
+    against: Option<&Path>,
+    against_profile: Option<&str>,
+    runs: usize,
+    warmup: usize,
+    output_limit: Option<usize>,
+) -> Result<TestOutcome, RunError> {
+    let config = get_config()?;
+
+    if !config.binary_dir_path.is_dir() {
+        return Err(RunError::Config("Binary path not found.".to_string()));
+    }
+
+    let mut variants = vec![Variant::new(src_path, profile)?];
+    if against.is_some() || against_profile.is_some() {
+        let other = Variant::new(against.unwrap_or(src_path), against_profile.or(profile))?;
+
+        if other.src_path == src_path && other.profile == profile {
+            return Err(RunError::Usage(format!(
+                "Nothing to compare, {} is benchmarked against itself.",
+                other.label
+            )));
+        }
+        variants.push(other);
+    }
+
+    let file_cache = prepare_binary(&config, src_path, profile, false)?;
+    for variant in &variants[1..] {
+        prepare_binary(&config, variant.src_path, variant.profile, false)?;
+    }
+
+    if file_cache.tests.is_empty() {
+        log!(info, "No test found.");
+        return Ok(TestOutcome::Passed);
+    }
+
+    let expression = expression.unwrap_or("*");
+    let selection = evaluate(expression, file_cache.tests.len())?;
+    let cases = selected_cases(&file_cache.tests, &selection)?;
+
+    if cases.is_empty() {
+        return Err(RunError::Usage(format!(
+            "No test matches \"{expression}\"."
+        )));
+    }
+
+    // Both variants run the tests of the first file, the way it reads and
+    // writes them.
+    let targets: Vec<ExecutionTarget> = variants
+        .iter()
+        .map(|variant| ExecutionTarget {
+            config: &config,
+            src_path: variant.src_path,
+            profile: variant.profile,
+            output_limit: effective_output_limit(&file_cache, output_limit),
+            time_limit: file_cache.time_limit,
+            memory_limit: file_cache.memory_limit,
+            io_files: file_cache.io_files.as_ref(),
+        })
+        .collect();
+
+    log!(
+        info,
+        "Benchmarking {} test{} with {runs} run{} each after {warmup} warmup run{}.",
+        cases.len(),
+        if cases.len() > 1 { "s" } else { "" },
+        if runs != 1 { "s" } else { "" },
+        if warmup != 1 { "s" } else { "" }
+    );
+    println!(
+        "{:<8} {:<20} {:>10} {:>10} {:>10} {:>10} {:>10}",
+        "Test".cyan(),
+        "Variant".cyan(),
+        "Min".cyan(),
+        "Median".cyan(),
+        "Mean".cyan(),
+        "Stddev".cyan(),
+        "Max".cyan()
+    );
+
+    let mut all_passed = true;
+    let (mut faster, mut slower, mut within_noise) = (0, 0, 0);
+
+    for case in &cases {
+        let measurements = targets
+            .iter()
+            .map(|target| measure(target, case, runs, warmup))
+            .collect::<Result<Vec<Measurement>, RunError>>()?;
+
+        for (index, (variant, measurement)) in variants.iter().zip(&measurements).enumerate() {
+            all_passed &= matches!(measurement, Measurement::Timed(_));
+
+            let change = match (index, &measurements[0], measurement) {
+                (1.., Measurement::Timed(baseline), Measurement::Timed(stats)) => {
+                    let change = Change::new(baseline, stats);
+                    match change {
+                        Change::Faster(_) => faster += 1,
+                        Change::Slower(_) => slower += 1,
+                        Change::WithinNoise => within_noise += 1,
+                    }
+                    change.to_colored_string()
+                }
+                _ => "".normal(),
+            };
+
+            print_row(
+                if index == 0 { &case.selector } else { "" },
+                &variant.label,
+                measurement,
+                change,
+            );
+        }
+    }
+
+    if let [baseline, other] = variants.as_slice() {
+        let compared = faster + slower + within_noise;
+        println!(
+            "{}",
+            format!(
+                "{} against {} over {compared} test{}: {faster} faster, {slower} slower, {within_noise} within noise.",
+                other.label,
+                baseline.label,
+                if compared > 1 { "s" } else { "" }
+            )
+            .blue()
+        );
+    }
+
+    if !all_passed {
+        log!(error, "Some tests didn't pass, their timings are left out.");
+    }
+
+    Ok(TestOutcome::from_all_passed(all_passed))
+}
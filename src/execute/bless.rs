@@ -0,0 +1,366 @@
+use super::core::{capture_output, ExecutionStatus, ExecutionTarget};
+use super::test::{_test_iterator, effective_output_limit};
+use super::{cache_key, prepare_binary, recompile_binary, ExecutionInput, RunError};
+use crate::cache_file::{
+    get_config, put_file, resolve_project_path, RunArgs, Test, TestLabels, Verdict,
+};
+use crate::log;
+use crate::section_file::{is_section_file, read_preamble, write_section_file};
+use crate::selector_evaluator::{evaluate, Selection, TestPosition};
+use crate::test_file::{SimpleTest, TestInput};
+
+use colored::Colorize;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Runs `input` and returns everything it printed, or how the run ended when
+/// it didn't exit successfully.
+fn produced_output(
+    target: &ExecutionTarget,
+    input: TestInput,
+    run_args: &RunArgs,
+) -> Result<Result<Vec<u8>, Verdict>, RunError> {
+    loop {
+        match capture_output(target, ExecutionInput::CustomInput(input.clone()), run_args)? {
+            ExecutionStatus::Successful { output, .. } => return Ok(Ok(output.stdout)),
+            ExecutionStatus::OutputLimitExceeded { .. } => {
+                return Ok(Err(Verdict::OutputLimitExceeded))
+            }
+            ExecutionStatus::TimeLimitExceeded { .. } => {
+                return Ok(Err(Verdict::TimeLimitExceeded))
+            }
+            ExecutionStatus::MemoryLimitExceeded { .. } => {
+                return Ok(Err(Verdict::MemoryLimitExceeded))
+            }
+            ExecutionStatus::Failed { .. } => return Ok(Err(Verdict::RuntimeError)),
+            ExecutionStatus::NeedRecompilation => {
+                recompile_binary(target.src_path, target.profile)?;
+            }
+        }
+    }
+}
+
+/// Why `output` would read back differently from a block of a test file.
+fn unwritable_reason(output: &[u8]) -> Option<&'static str> {
+    let mut depth = 0;
+
+    for line in output.split(|&byte| byte == b'\n') {
+        if line.trim_ascii_start().starts_with(b"#") {
+            return Some("a line starts with '#'");
+        }
+        if line.contains(&b'\r') {
+            return Some("it contains a carriage return");
+        }
+        if line.windows(2).any(|pair| pair == b"->" || pair == b"\\n") || line.ends_with(b"\\") {
+            return Some("it contains \"->\" or a backslash the test file would read as an escape");
+        }
+
+        for &byte in line {
+            match byte {
+                b'{' => depth += 1,
+                b'}' if depth == 0 => return Some("its braces aren't balanced"),
+                b'}' => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    (depth != 0).then_some("its braces aren't balanced")
+}
+
+/// Writes `outputs` as a standalone test file, kept untrimmed so it reads
+/// back exactly.
+fn write_output_file(path: &Path, outputs: &[Vec<u8>]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create_new(path)?);
+
+    writeln!(writer, "#standalone")?;
+    writeln!(writer, "#disable: trim")?;
+    for output in outputs {
+        writer.write_all(b"{")?;
+        writer.write_all(output)?;
+        writer.write_all(b"}\n")?;
+    }
+
+    writer.flush()
+}
+
+/// `tests/a.txt` is blessed into `tests/a.out.txt`.
+fn output_file_path(input: &Path) -> PathBuf {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+
+    input.with_file_name(match input.extension() {
+        Some(extension) => format!("{stem}.out.{}", extension.to_string_lossy()),
+        None => format!("{stem}.out"),
+    })
+}
+
+/// Blesses every block of the test file `input` into an output file next to
+/// it, which is returned. Blocks are linked by position, so the whole file
+/// is blessed as soon as one of its blocks is selected. Reasons a file can't
+/// be blessed are returned as usage errors.
+fn bless_test_file(
+    target: &ExecutionTarget,
+    main_index: usize,
+    input: &Path,
+    run_args: &RunArgs,
+    labels: &TestLabels,
+    selection: &Selection,
+) -> Result<Option<PathBuf>, RunError> {
+    let sub_tests = _test_iterator(input, None)?
+        .collect::<Result<Vec<SimpleTest>, _>>()
+        .map_err(|err| RunError::Parse(err.to_string()))?;
+    let sub_test_count = sub_tests.len();
+
+    let selected = (1..=sub_test_count)
+        .zip(&sub_tests)
+        .any(|(sub_index, sub_test)| {
+            selection.selects(
+                TestPosition::sub_test(main_index, sub_index, Some(sub_test_count)),
+                &[labels, &sub_test.labels],
+            )
+        });
+    if !selected {
+        return Ok(None);
+    }
+
+    let input_only = sub_tests
+        .iter()
+        .filter(|sub_test| sub_test.expected_output.is_none())
+        .count();
+    if input_only == 0 {
+        return Ok(None);
+    }
+    if input_only < sub_test_count {
+        return Err(RunError::Usage(format!(
+            "Test #{main_index} mixes blocks with and without an expected output, it can't be blessed."
+        )));
+    }
+
+    let output_path = output_file_path(input);
+    let resolved_output_path = resolve_project_path(&output_path)?;
+    if resolved_output_path.exists() {
+        return Err(RunError::Usage(format!(
+            "{resolved_output_path:?} already exists, remove it to bless test #{main_index}."
+        )));
+    }
+
+    let mut outputs = Vec::with_capacity(sub_test_count);
+    for (sub_index, sub_test) in (1..).zip(sub_tests) {
+        let output = match produced_output(
+            target,
+            sub_test.input,
+            &run_args.merged_with(&sub_test.run_args),
+        )? {
+            Ok(output) => output,
+            Err(verdict) => {
+                return Err(RunError::Usage(format!(
+                    "Sub-test #{main_index}.{sub_index} can't be blessed, it ended with {verdict}."
+                )))
+            }
+        };
+
+        if let Some(reason) = unwritable_reason(&output) {
+            return Err(RunError::Usage(format!(
+                "Output of sub-test #{main_index}.{sub_index} can't be written to a test file, {reason}."
+            )));
+        }
+        outputs.push(output);
+    }
+
+    write_output_file(&resolved_output_path, &outputs)?;
+    log!(
+        success,
+        "Blessed test #{main_index} into {resolved_output_path:?}."
+    );
+
+    Ok(Some(output_path))
+}
+
+/// Blesses the selected cases of the section file `input` that have no
+/// expected output by writing their outputs into it, and returns how many
+/// were. Unlike blocks, cases are blessed one by one.
+fn bless_section_file(
+    target: &ExecutionTarget,
+    main_index: usize,
+    input: &Path,
+    run_args: &RunArgs,
+    labels: &TestLabels,
+    selection: &Selection,
+) -> Result<usize, RunError> {
+    let mut sub_tests = _test_iterator(input, None)?
+        .collect::<Result<Vec<SimpleTest>, _>>()
+        .map_err(|err| RunError::Parse(err.to_string()))?;
+    let sub_test_count = sub_tests.len();
+    let mut blessed = 0;
+
+    for (sub_index, sub_test) in (1..).zip(&mut sub_tests) {
+        let position = TestPosition::sub_test(main_index, sub_index, Some(sub_test_count));
+        if sub_test.expected_output.is_some()
+            || !selection.selects(position, &[labels, &sub_test.labels])
+        {
+            continue;
+        }
+
+        match produced_output(
+            target,
+            sub_test.input.clone(),
+            &run_args.merged_with(&sub_test.run_args),
+        )? {
+            Ok(output) => {
+                sub_test.expected_output = Some(output.into());
+                blessed += 1;
+            }
+            Err(verdict) => {
+                log!(
+                    warn,
+                    "Sub-test #{main_index}.{sub_index} can't be blessed, it ended with {verdict}."
+                );
+            }
+        }
+    }
+
+    if blessed > 0 {
+        let path = resolve_project_path(input)?;
+        let mut contents = Vec::new();
+        write_section_file(&mut contents, Some(&read_preamble(&path)?), &sub_tests)?;
+        fs::write(&path, contents)?;
+
+        log!(
+            success,
+            "Blessed {blessed} sub-test{} of test #{main_index} in {path:?}.",
+            if blessed > 1 { "s" } else { "" }
+        );
+    }
+
+    Ok(blessed)
+}
+
+/// Runs the selected tests that have no expected output and saves what they
+/// printed as their expected output.
+pub fn bless(
+    src_path: &Path,
+    expression: Option<&str>,
+    profile: Option<&str>,
+) -> Result<(), RunError> {
+    let config = get_config()?;
+
+    if !config.binary_dir_path.is_dir() {
+        return Err(RunError::Config("Binary path not found.".to_string()));
+    }
+
+    let mut file_cache = prepare_binary(&config, src_path, profile, false)?;
+
+    if file_cache.tests.is_empty() {
+        log!(info, "No test found.");
+        return Ok(());
+    }
+
+    let expression = expression.unwrap_or("*");
+    let selection = evaluate(expression, file_cache.tests.len())?;
+
+    let io_files = file_cache.io_files.clone();
+    let target = ExecutionTarget {
+        config: &config,
+        src_path,
+        profile,
+        output_limit: effective_output_limit(&file_cache, None),
+        time_limit: file_cache.time_limit,
+        memory_limit: file_cache.memory_limit,
+        io_files: io_files.as_ref(),
+    };
+
+    let mut blessed = 0;
+
+    for (index, test) in file_cache.tests.iter_mut().enumerate() {
+        let main_index = index + 1;
+
+        if !selection.may_select(main_index, test.labels()) {
+            continue;
+        }
+
+        // Tests with an expected output are left as they are.
+        match test {
+            Test::StringTest {
+                input,
+                expected_output: expected_output @ None,
+                run_args,
+                labels,
+            } => {
+                if !selection.selects(TestPosition::test(main_index), &[labels]) {
+                    continue;
+                }
+
+                match produced_output(&target, input.as_bytes().to_vec().into(), run_args)? {
+                    Ok(output) => match String::from_utf8(output) {
+                        Ok(output) => {
+                            *expected_output = Some(output);
+                            blessed += 1;
+                            log!(success, "Blessed test #{main_index}.");
+                        }
+                        Err(_) => {
+                            log!(
+                                warn,
+                                "Output of test #{main_index} isn't valid UTF-8, link it from a test file instead."
+                            );
+                        }
+                    },
+                    Err(verdict) => {
+                        log!(
+                            warn,
+                            "Test #{main_index} can't be blessed, it ended with {verdict}."
+                        );
+                    }
+                }
+            }
+
+            // Section files hold their outputs, so they are blessed in place.
+            Test::RefTest {
+                input,
+                expected_output: None,
+                run_args,
+                labels,
+            } if is_section_file(&resolve_project_path(input)?).unwrap_or(false) => {
+                blessed +=
+                    (bless_section_file(&target, main_index, input, run_args, labels, &selection)?
+                        > 0) as usize;
+            }
+
+            Test::RefTest {
+                input,
+                expected_output: expected_output @ None,
+                run_args,
+                labels,
+            } => match bless_test_file(&target, main_index, input, run_args, labels, &selection) {
+                Ok(Some(output_path)) => {
+                    *expected_output = Some(output_path);
+                    blessed += 1;
+                }
+                Ok(None) => {}
+                Err(RunError::Usage(reason)) => {
+                    log!(warn, "{reason}");
+                }
+                Err(err) => return Err(err),
+            },
+
+            _ => {}
+        }
+    }
+
+    if blessed == 0 {
+        log!(
+            info,
+            "No test without an expected output was blessed for \"{expression}\"."
+        );
+        return Ok(());
+    }
+
+    put_file(cache_key(src_path)?, file_cache)?;
+    log!(
+        success,
+        "Blessed {blessed} test{} of {src_path:?}.",
+        if blessed > 1 { "s" } else { "" }
+    );
+
+    Ok(())
+}
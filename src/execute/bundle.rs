@@ -0,0 +1,86 @@
+use super::core::ExecutionTarget;
+use super::test::{effective_output_limit, run_tests, TestOutcome};
+use super::{cache_key, RunError};
+use crate::bundler::bundle_source;
+use crate::cache_file::{get_config, get_file};
+use crate::execute::recompile_binary;
+use crate::log;
+
+use colored::Colorize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Path the bundle of `path` is written to, inside the binary directory and
+/// keeping the extension so it builds with the same language profile.
+fn bundle_path(binary_dir_path: &Path, path: &Path) -> Result<PathBuf, RunError> {
+    let (Some(stem), Some(extension)) = (path.file_stem(), path.extension()) else {
+        return Err(RunError::Usage(format!(
+            "Unable to determine the bundle name of {path:?}."
+        )));
+    };
+
+    let mut filename = stem.to_os_string();
+    filename.push("_bundle.");
+    filename.push(extension);
+
+    Ok(binary_dir_path.join(filename))
+}
+
+pub fn bundle(path: &Path, verify: bool, profile: Option<&str>) -> Result<TestOutcome, RunError> {
+    let config = get_config()?;
+
+    if !config.binary_dir_path.is_dir() {
+        return Err(RunError::Config("Binary path not found.".to_string()));
+    }
+
+    let bundled = bundle_source(path)?;
+    let bundle_path = bundle_path(&config.binary_dir_path, path)?;
+
+    fs::write(&bundle_path, bundled)?;
+    log!(success, "Bundled {path:?} into {bundle_path:?}.");
+
+    if !verify {
+        return Ok(TestOutcome::Passed);
+    }
+
+    log!(info, "Verifying bundle {bundle_path:?}...");
+    recompile_binary(&bundle_path, profile)?;
+    log!(success, "Bundle compiled successfully.");
+
+    let filename = cache_key(path)?;
+    let Some(file_cache) = get_file(filename)
+        .ok()
+        .flatten()
+        .filter(|file_cache| !file_cache.tests.is_empty())
+    else {
+        log!(
+            info,
+            "No test found for {path:?}, skipping test verification."
+        );
+        return Ok(TestOutcome::Passed);
+    };
+
+    let target = ExecutionTarget {
+        config: &config,
+        src_path: &bundle_path,
+        profile,
+        output_limit: effective_output_limit(&file_cache, None),
+        time_limit: file_cache.time_limit,
+        memory_limit: file_cache.memory_limit,
+        io_files: file_cache.io_files.as_ref(),
+    };
+
+    // Verdicts of the bundle aren't kept, they belong to the source file.
+    let score = run_tests(
+        &file_cache.tests,
+        &target,
+        false,
+        false,
+        &mut BTreeMap::new(),
+    )?;
+
+    Ok(TestOutcome::from_all_passed(
+        score == file_cache.tests.len(),
+    ))
+}
@@ -1,14 +1,17 @@
-use super::{put_config, sha256_digest, Files};
+use super::{put_config, Files, RunError};
 use crate::{
-    cache_file::{get_config, FileCache},
-    execute::core::recompile_binary,
+    cache_file::{get_config, to_project_path, BuildKey, FileCache, Test},
+    execute::core::{current_build_key, recompile_binary},
     log,
 };
 
 use colored::Colorize;
-use data_encoding::HEXUPPER;
 use std::fs;
-use std::{collections::HashMap, io, path::Path};
+use std::{
+    collections::HashMap,
+    io,
+    path::{Path, PathBuf},
+};
 
 pub fn purge() -> io::Result<()> {
     log!(warn, "This operation will permanently remove all registered tests. This action cannot be undone.");
@@ -65,27 +68,32 @@ pub fn clean() -> io::Result<()> {
     Ok(())
 }
 
-pub fn recompile(all: bool) -> io::Result<()> {
-    let config = get_config()?;
+pub fn recompile(all: bool) -> Result<(), RunError> {
+    let mut config = get_config()?;
     let mut recompiled_numbers = 0u32;
+    let mut rebuilt_files: Vec<(String, BuildKey)> = Vec::new();
 
-    for filename in config.files.keys() {
+    for (filename, file_cache) in config.files.iter() {
         if Path::new(filename).exists() {
-            let target_hashed = HEXUPPER
-                .encode(sha256_digest(io::BufReader::new(fs::File::open(filename)?))?.as_ref());
-
-            if all || target_hashed != config.files.get(filename).unwrap().source_hash {
-                recompile_binary(&fs::canonicalize(Path::new(filename))?).map_err(|err| {
-                    io::Error::new(
-                        io::ErrorKind::Other,
-                        format!("Unable to recompile file: {err}."),
-                    )
-                })?;
+            let path = fs::canonicalize(Path::new(filename))?;
+            let build_key = current_build_key(&config, &path, None)?;
+
+            if all || file_cache.build_key(None) != Some(&build_key) {
+                recompile_binary(&path, None)?;
+                rebuilt_files.push((filename.clone(), build_key));
                 recompiled_numbers += 1;
             }
         }
     }
 
+    for (filename, build_key) in rebuilt_files {
+        if let Some(file_cache) = config.files.get_mut(&filename) {
+            file_cache.set_build_key(None, build_key);
+        }
+    }
+
+    put_config(config)?;
+
     log!(
         info,
         "Successfully recompiled {} file{}.",
@@ -95,3 +103,57 @@ pub fn recompile(all: bool) -> io::Result<()> {
 
     Ok(())
 }
+
+pub fn relink(old_prefix: &Path, new_prefix: &Path) -> io::Result<()> {
+    let mut config = get_config()?;
+    let mut relinked_numbers = 0u32;
+
+    let relink_path = |path: &mut PathBuf| -> io::Result<bool> {
+        let Ok(suffix) = path.strip_prefix(old_prefix) else {
+            return Ok(false);
+        };
+
+        *path = to_project_path(&new_prefix.join(suffix))?;
+        Ok(true)
+    };
+
+    for file_cache in config.files.values_mut() {
+        for test in file_cache.tests.iter_mut() {
+            if let Test::RefTest {
+                input,
+                expected_output,
+                ..
+            } = test
+            {
+                let mut relinked = relink_path(input)?;
+
+                if let Some(expected_output) = expected_output {
+                    relinked |= relink_path(expected_output)?;
+                }
+
+                relinked_numbers += relinked as u32;
+            }
+        }
+    }
+
+    if relink_path(&mut config.binary_dir_path)? {
+        log!(
+            info,
+            "Relinked binary directory to {:?}.",
+            config.binary_dir_path
+        );
+    }
+
+    put_config(config)?;
+
+    log!(
+        info,
+        "Relinked {} test{} from {:?} to {:?}.",
+        relinked_numbers,
+        if relinked_numbers > 1 { "s" } else { "" },
+        old_prefix,
+        new_prefix
+    );
+
+    Ok(())
+}
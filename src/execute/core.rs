@@ -1,151 +1,700 @@
-use crate::cache_file::{get_config, template_config_replacement};
+use super::RunError;
+use crate::cache_file::{
+    binary_path, expand_command, get_config, project_root, resolve_project_path, to_project_path,
+    BuildKey, Files, IoFiles, RunArgs,
+};
+use crate::dependency_scanner::scan_dependencies;
+use crate::diagnostics::{parse_diagnostics, print_diagnostics, CompilationReport};
+use crate::limits::{self, Exit, Watchdog};
 use crate::log;
+use crate::sandbox::{spawn_isolated, TempDir};
+use crate::test_file::TestInput;
+use crate::utils::{append_extension, sha256_digest};
 use colored::Colorize;
-use shell_words;
-use std::ffi;
+use data_encoding::HEXUPPER;
+use std::env;
+use std::fs;
 use std::io;
-use std::io::BufWriter;
-use std::io::Write;
-use std::path::Path;
-use std::process::{Command, Output, Stdio};
+use std::io::{BufRead, BufReader, BufWriter, Read};
+use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::process::{Command, Stdio};
 use std::thread;
 use std::time::Duration;
-use std::time::Instant;
+use std::time::{Instant, UNIX_EPOCH};
+
+/// Leading bytes of stdout kept for display, the rest is only compared.
+pub const RETAINED_OUTPUT_BYTES: usize = 8 * 1024;
+
+/// A run is stopped once it printed more than this many bytes, unless the
+/// file or the command line sets another limit.
+pub const DEFAULT_OUTPUT_LIMIT: usize = 64 * 1024 * 1024;
 
 pub enum ExecutionInput {
     InheritFromTerminal,
-    CustomInput(String),
+    CustomInput(TestInput),
+}
+
+/// What a run with piped output printed.
+pub struct CapturedOutput {
+    /// The first `RETAINED_OUTPUT_BYTES` of stdout, or of the output file,
+    /// everything for `capture_output`.
+    pub stdout: Vec<u8>,
+    /// How many bytes were printed in total.
+    pub total_bytes: usize,
+    /// Whether stdout matched the expected output, when one was given.
+    pub matches: Option<bool>,
 }
 
 pub enum ExecutionStatus {
+    /// The binary exited successfully, or was stopped as soon as its output
+    /// diverged from the expected one.
     Successful {
-        output: Output,
+        output: CapturedOutput,
+        time_elapsed: Duration,
+    },
+    OutputLimitExceeded {
+        output: CapturedOutput,
+        time_elapsed: Duration,
+    },
+    /// Killed once it ran past the time limit.
+    TimeLimitExceeded {
+        time_elapsed: Duration,
+    },
+    MemoryLimitExceeded {
+        peak_memory: usize,
         time_elapsed: Duration,
     },
     NeedRecompilation,
-    Failed(String),
+    /// The binary exited unsuccessfully, with what it printed until then.
+    Failed {
+        message: String,
+        output: CapturedOutput,
+        time_elapsed: Duration,
+    },
 }
 
-pub fn recompile_binary(src_path: &Path) -> Result<(), String> {
-    let file_type = src_path
-        .extension()
-        .and_then(ffi::OsStr::to_str)
-        .ok_or_else(|| "Failed to determine file type".to_string())?;
+/// Compares output against the expected one as both stream in, ignoring
+/// surrounding whitespace like the judges do.
+struct OutputComparator {
+    expected_output: BufReader<Box<dyn Read + Send>>,
+    started: bool,
+    /// Set once both went on with different whitespace, after which only
+    /// whitespace may be left in either.
+    trailing: bool,
+    diverged: bool,
+}
 
-    let mut config = get_config().map_err(|err| format!("Config error: {}", err))?;
+impl OutputComparator {
+    fn new(expected_output: &TestInput) -> io::Result<Self> {
+        let mut expected_output = BufReader::new(expected_output.reader()?);
 
-    let mut sys_call = config
-        .languages_config
-        .remove(file_type)
-        .ok_or_else(|| format!("File type \"{}\" is not supported.", file_type))?;
+        loop {
+            let buffer = expected_output.fill_buf()?;
+            let leading = buffer
+                .iter()
+                .take_while(|byte| byte.is_ascii_whitespace())
+                .count();
+            let started = leading < buffer.len();
+            expected_output.consume(leading);
+
+            if started || leading == 0 {
+                break;
+            }
+        }
 
-    template_config_replacement(&mut sys_call, config.binary_dir_path.as_path(), src_path)
-        .map_err(|err| format!("Template error: {}", err))?;
+        Ok(Self {
+            expected_output,
+            started: false,
+            trailing: false,
+            diverged: false,
+        })
+    }
 
-    let sys_call: Vec<String> =
-        shell_words::split(&sys_call).map_err(|_| "Failed to parse command".to_string())?;
+    /// Feeds the next chunk of output, returning false once it can no longer match.
+    fn feed(&mut self, chunk: &[u8]) -> io::Result<bool> {
+        for &byte in chunk {
+            if !self.started {
+                if byte.is_ascii_whitespace() {
+                    continue;
+                }
+                self.started = true;
+            }
 
-    if sys_call.is_empty() {
-        return Err("System call command is empty".to_string());
+            if !self.trailing {
+                match self.expected_output.fill_buf()?.first() {
+                    Some(&expected) if expected == byte => {
+                        self.expected_output.consume(1);
+                        continue;
+                    }
+                    Some(expected) if !expected.is_ascii_whitespace() => {}
+                    _ => self.trailing = true,
+                }
+            }
+            if !self.trailing || !byte.is_ascii_whitespace() {
+                self.diverged = true;
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Whether the output matched, once all of it was fed.
+    fn matches(&mut self) -> io::Result<bool> {
+        if self.diverged {
+            return Ok(false);
+        }
+
+        loop {
+            let buffer = self.expected_output.fill_buf()?;
+            if buffer.is_empty() {
+                return Ok(true);
+            }
+            if !buffer.iter().all(u8::is_ascii_whitespace) {
+                return Ok(false);
+            }
+            let read = buffer.len();
+            self.expected_output.consume(read);
+        }
     }
+}
+
+/// Why a run was stopped before it exited on its own.
+enum EarlyAbort {
+    OutputDiverged,
+    OutputLimitExceeded,
+}
+
+/// The source file being executed together with the configuration and
+/// build profile used to build and run it.
+pub struct ExecutionTarget<'a> {
+    pub config: &'a Files,
+    pub src_path: &'a Path,
+    pub profile: Option<&'a str>,
+    /// Piped runs are stopped once they print more than this many bytes.
+    pub output_limit: usize,
+    /// Piped runs are killed once they take longer.
+    pub time_limit: Option<Duration>,
+    /// Peak memory in bytes piped runs may use, checked once they exit.
+    pub memory_limit: Option<usize>,
+    /// Files the solution reads and writes instead of stdin and stdout.
+    pub io_files: Option<&'a IoFiles>,
+}
+
+/// Computes the key the binary of `src_path` would be built with right now.
+pub fn current_build_key(
+    config: &Files,
+    src_path: &Path,
+    profile: Option<&str>,
+) -> Result<BuildKey, RunError> {
+    let source_file =
+        fs::File::open(src_path).map_err(io_context(format!("Unable to read {src_path:?}")))?;
+    let source_hash = HEXUPPER.encode(
+        sha256_digest(io::BufReader::new(source_file))
+            .map_err(io_context(format!("Unable to hash {src_path:?}")))?
+            .as_ref(),
+    );
+
+    let language_profile = config
+        .language_profile(src_path, profile)
+        .map_err(RunError::Config)?;
+
+    // Interpreted languages only depend on their source.
+    let Some(compile) = &language_profile.compile else {
+        return Ok(BuildKey {
+            source_hash,
+            dependencies_hash: String::new(),
+            command: String::new(),
+            toolchain_hash: String::new(),
+        });
+    };
+
+    // Paths are hashed relative to the project, so moving it keeps its builds.
+    let project_root =
+        project_root().map_err(io_context("Unable to resolve the project root".to_string()))?;
+    let sys_call = expand_command(
+        compile,
+        &project_root.join(&config.binary_dir_path),
+        src_path,
+        profile,
+    )
+    .map_err(RunError::Config)?;
+    let project_prefix = format!("{}{MAIN_SEPARATOR}", project_root.display());
+    let command = sys_call
+        .iter()
+        .map(|argument| argument.replace(&project_prefix, "./"))
+        .collect::<Vec<_>>();
+
+    Ok(BuildKey {
+        source_hash,
+        dependencies_hash: dependencies_hash(src_path)?,
+        toolchain_hash: toolchain_fingerprint(&sys_call[0]),
+        command: shell_words::join(&command),
+    })
+}
+
+/// Hashes the content of every local header or module `src_path` pulls in,
+/// empty when it has none.
+fn dependencies_hash(src_path: &Path) -> Result<String, RunError> {
+    let dependencies = scan_dependencies(src_path).map_err(io_context(format!(
+        "Unable to scan dependencies of {src_path:?}"
+    )))?;
+
+    if dependencies.is_empty() {
+        return Ok(String::new());
+    }
+
+    let mut fingerprint = String::new();
+
+    for dependency in dependencies {
+        let dependency_file = fs::File::open(&dependency)
+            .map_err(io_context(format!("Unable to read {dependency:?}")))?;
+        let dependency_hash = sha256_digest(io::BufReader::new(dependency_file))
+            .map_err(io_context(format!("Unable to hash {dependency:?}")))?;
+
+        let dependency_path = to_project_path(&dependency)
+            .map_err(io_context(format!("Unable to resolve {dependency:?}")))?;
+        fingerprint.push_str(&format!(
+            "{}:{}\n",
+            dependency_path.display(),
+            HEXUPPER.encode(dependency_hash.as_ref())
+        ));
+    }
+
+    sha256_digest(fingerprint.as_bytes())
+        .map(|digest| HEXUPPER.encode(digest.as_ref()))
+        .map_err(io_context(format!(
+            "Unable to hash dependencies of {src_path:?}"
+        )))
+}
+
+/// Wraps an I/O error with what erunner was doing when it happened.
+fn io_context(context: String) -> impl FnOnce(io::Error) -> RunError {
+    move |err| RunError::Io(io::Error::new(err.kind(), format!("{context}: {err}")))
+}
+
+/// Fingerprints `compiler` by the path, size and modification time of its
+/// binary. It is never run, so `status` doesn't execute whatever program a
+/// compile command names. "unknown" when the binary can't be found.
+fn toolchain_fingerprint(compiler: &str) -> String {
+    let fingerprint = find_program(compiler).and_then(|compiler_path| {
+        let metadata = fs::metadata(&compiler_path).ok()?;
+        let compiler_path = to_project_path(&compiler_path).ok()?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+
+        Some(format!(
+            "{}:{}:{}",
+            compiler_path.display(),
+            metadata.len(),
+            modified.as_nanos()
+        ))
+    });
+
+    fingerprint
+        .and_then(|fingerprint| sha256_digest(fingerprint.as_bytes()).ok())
+        .map_or_else(
+            || "unknown".to_string(),
+            |digest| HEXUPPER.encode(digest.as_ref()),
+        )
+}
+
+/// Resolves `program` to its binary the way spawning it does, looking it up
+/// in `PATH` unless it is a path.
+fn find_program(program: &str) -> Option<PathBuf> {
+    let program = Path::new(program);
+    if program.components().count() > 1 {
+        return fs::canonicalize(program).ok();
+    }
+
+    for directory in env::split_paths(&env::var_os("PATH")?) {
+        let candidate = directory.join(program);
+        if candidate.is_file() {
+            return fs::canonicalize(candidate).ok();
+        }
+
+        let candidate = append_extension("exe", candidate);
+        if cfg!(windows) && candidate.is_file() {
+            return fs::canonicalize(candidate).ok();
+        }
+    }
+
+    None
+}
+
+pub fn recompile_binary(src_path: &Path, profile: Option<&str>) -> Result<(), RunError> {
+    let config = get_config()?;
+    let language_profile = config
+        .language_profile(src_path, profile)
+        .map_err(RunError::Config)?;
+
+    // Interpreted languages have nothing to build.
+    let Some(compile) = &language_profile.compile else {
+        return Ok(());
+    };
+
+    let sys_call = expand_command(compile, config.binary_dir_path.as_path(), src_path, profile)
+        .map_err(RunError::Config)?;
 
     let command = &sys_call[0];
     let args = &sys_call[1..];
 
     let output = Command::new(command)
         .args(args)
-        .stdout(Stdio::inherit())
-        .stderr(Stdio::inherit())
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
         .output()
-        .map_err(|err| format!("Command execution error: {}", err))?;
+        .map_err(|err| RunError::Config(format!("Unable to run compiler {command:?}: {err}")))?;
+
+    let binary_path = binary_path(&config.binary_dir_path, src_path, profile)
+        .map_err(|err| RunError::Config(format!("Template error: {err}")))?;
+
+    let compiler_output = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stderr),
+        String::from_utf8_lossy(&output.stdout)
+    );
+
+    let report = CompilationReport {
+        source: src_path.to_path_buf(),
+        success: output.status.success(),
+        diagnostics: parse_diagnostics(&compiler_output),
+    };
 
-    if output.status.success() {
-        Ok(())
+    write_compilation_report(&binary_path, &report);
+
+    if !report.success && report.error_count() == 0 {
+        // The error wasn't recognised, e.g. one of the linker, so the output
+        // is shown verbatim rather than only the warnings parsed from it.
+        print!("{compiler_output}");
     } else {
-        Err(format!("Failed to compile {:?}", src_path))
+        print_diagnostics(&report.diagnostics);
+    }
+
+    if !report.success {
+        return Err(RunError::CompilationError(match report.first_error() {
+            Some(diagnostic) => format!(
+                "Failed to compile {:?} with {} error{}, first at {}:{}.",
+                src_path,
+                report.error_count(),
+                if report.error_count() > 1 { "s" } else { "" },
+                diagnostic.file.display(),
+                diagnostic.line
+            ),
+            None => format!("Failed to compile {:?}", src_path),
+        }));
+    }
+
+    if !binary_path.exists() {
+        return Err(RunError::Config(format!(
+            "Compile command did not produce {:?}, make sure it writes to $(BINARY).",
+            binary_path
+        )));
+    }
+
+    Ok(())
+}
+
+/// Saves the diagnostics of the last compilation as `<binary>.diagnostics.json`.
+fn write_compilation_report(binary_path: &Path, report: &CompilationReport) {
+    let report_path = append_extension("diagnostics.json", binary_path.to_path_buf());
+
+    let written = fs::File::create(&report_path)
+        .map_err(serde_json::Error::io)
+        .and_then(|file| serde_json::to_writer_pretty(BufWriter::new(file), report));
+
+    if written.is_err() {
+        log!(warn, "Unable to write compilation report {report_path:?}.");
     }
 }
 
+/// Runs the binary of `target`. Piped output is compared against
+/// `expected_output` while it streams in, so the run can be stopped early and
+/// only a bounded prefix of it is ever held in memory.
 pub fn execute_binary(
-    binary_dir_path: &Path,
-    filename: &str,
+    target: &ExecutionTarget,
     input: ExecutionInput,
-) -> Result<ExecutionStatus, io::Error> {
-    let binary_name = if cfg!(windows) {
-        format!("{filename}.exe")
-    } else {
-        format!("{filename}.out")
-    };
+    run_args: &RunArgs,
+    expected_output: Option<&TestInput>,
+) -> Result<ExecutionStatus, RunError> {
+    run_binary(
+        target,
+        input,
+        run_args,
+        expected_output,
+        RETAINED_OUTPUT_BYTES,
+    )
+}
 
-    let binary_path = binary_dir_path.join(&binary_name);
+/// Runs the binary of `target` and keeps its whole output, up to the output
+/// limit, to compare another run against.
+pub fn capture_output(
+    target: &ExecutionTarget,
+    input: ExecutionInput,
+    run_args: &RunArgs,
+) -> Result<ExecutionStatus, RunError> {
+    run_binary(target, input, run_args, None, target.output_limit)
+}
 
-    if !binary_path.exists() {
+fn run_binary(
+    target: &ExecutionTarget,
+    input: ExecutionInput,
+    run_args: &RunArgs,
+    expected_output: Option<&TestInput>,
+    retained_bytes: usize,
+) -> Result<ExecutionStatus, RunError> {
+    let ExecutionTarget {
+        config,
+        src_path,
+        profile,
+        output_limit,
+        time_limit,
+        memory_limit,
+        io_files,
+    } = *target;
+
+    let language_profile = config
+        .language_profile(src_path, profile)
+        .map_err(RunError::Config)?;
+
+    // Sandboxed and file based runs happen in another directory, where a
+    // binary directory stored relative to the project can't be found.
+    let binary_dir_path = resolve_project_path(&config.binary_dir_path)?;
+
+    if language_profile.compile.is_some()
+        && !binary_path(&binary_dir_path, src_path, profile)
+            .map_err(|err| RunError::Config(format!("Template error: {err}")))?
+            .exists()
+    {
         return Ok(ExecutionStatus::NeedRecompilation);
     }
 
+    // Limits of a test take precedence over those of its file.
+    let time_limit = run_args.time_limit.or(time_limit);
+    let memory_limit = run_args.memory_limit.or(memory_limit);
+
+    let sys_call = expand_command(&language_profile.run, &binary_dir_path, src_path, profile)
+        .map_err(RunError::Config)?;
+
+    // File based problems read and write named files in their working
+    // directory, terminal runs always use the current directory.
+    let io_files = match input {
+        ExecutionInput::CustomInput(_) => io_files,
+        ExecutionInput::InheritFromTerminal => None,
+    };
+    let piped = |stdio: Stdio| match (&input, io_files) {
+        (ExecutionInput::InheritFromTerminal, _) => Stdio::inherit(),
+        (ExecutionInput::CustomInput(_), Some(_)) => Stdio::null(),
+        (ExecutionInput::CustomInput(_), None) => stdio,
+    };
+
+    let mut command = Command::new(&sys_call[0]);
+    command
+        .args(&sys_call[1..])
+        .args(&run_args.args)
+        .envs(&run_args.env)
+        .stdin(piped(Stdio::piped()))
+        .stdout(piped(Stdio::piped()));
+
+    // Kept until the run is over, the directory is removed on drop.
+    let working_dir = match config.sandbox || io_files.is_some() {
+        true => Some(
+            TempDir::new().map_err(io_context("Failed to create working directory".to_string()))?,
+        ),
+        false => None,
+    };
+
+    if let (Some(io_files), Some(working_dir), ExecutionInput::CustomInput(input_data)) =
+        (io_files, &working_dir, &input)
+    {
+        let input_path = working_dir.path().join(&io_files.input);
+        input_data
+            .reader()
+            .and_then(|mut reader| io::copy(&mut reader, &mut fs::File::create(&input_path)?))
+            .map_err(io_context(format!("Failed to write {input_path:?}")))?;
+    }
+
+    // Opened before the run starts, so a missing test file isn't noticed
+    // with the binary already running.
+    let input_reader = match (&input, io_files) {
+        (ExecutionInput::CustomInput(input_data), None) => Some(
+            input_data
+                .reader()
+                .map_err(io_context("Failed to read test input".to_string()))?,
+        ),
+        _ => None,
+    };
+    let mut comparator = expected_output
+        .map(OutputComparator::new)
+        .transpose()
+        .map_err(io_context("Failed to read expected output".to_string()))?;
+
     let now = Instant::now();
 
-    let mut child = Command::new(&binary_path)
-        .stdin(match &input {
-            ExecutionInput::InheritFromTerminal => Stdio::inherit(),
-            ExecutionInput::CustomInput(_) => Stdio::piped(),
-        })
-        .stdout(match &input {
-            ExecutionInput::InheritFromTerminal => Stdio::inherit(),
-            ExecutionInput::CustomInput(_) => Stdio::piped(),
-        })
-        .spawn()
-        .map_err(|err| {
-            io::Error::new(
-                io::ErrorKind::Other,
-                format!("Failed to spawn binary: {err}"),
-            )
-        })?;
+    let mut child = match &working_dir {
+        Some(working_dir) if config.sandbox => spawn_isolated(&mut command, working_dir.path()),
+        Some(working_dir) => command.current_dir(working_dir.path()).spawn(),
+        None => command.spawn(),
+    }
+    .map_err(io_context("Failed to spawn binary".to_string()))?;
+
+    // Terminal runs are interactive, so they take as long as they need.
+    let watchdog = match (&input, time_limit) {
+        (ExecutionInput::CustomInput(_), Some(time_limit)) => {
+            Some(Watchdog::start(&child, time_limit))
+        }
+        _ => None,
+    };
 
     // Handle custom input in a separate thread
-    if let ExecutionInput::CustomInput(input_data) = input {
-        if let Some(child_stdin) = child.stdin.take() {
-            let input_data = input_data.clone();
-
-            // Spawn a new thread to write input in chunks
-            thread::spawn(move || {
-                let mut writer = BufWriter::new(child_stdin);
-                const CHUNK_SIZE: usize = 1024;
-
-                for chunk in input_data.as_bytes().chunks(CHUNK_SIZE) {
-                    if writer.write_all(chunk).is_err() {
-                        log!(error, "failed to write chunk to child process stdin. The process may have closed its stdin or encountered an error.");
-                        break;
-                    }
+    if let (Some(mut input_reader), Some(mut child_stdin)) = (input_reader, child.stdin.take()) {
+        // Spawn a new thread streaming the input in as the process reads it
+        thread::spawn(
+            move || match io::copy(&mut input_reader, &mut child_stdin) {
+                Ok(_) => {}
+                // The process exited or was stopped without reading all of its input.
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+                Err(_) => {
+                    log!(error, "failed to write input to child process stdin. The process may have closed its stdin or encountered an error.");
                 }
+            },
+        );
+    }
 
-                if writer.flush().is_err() {
-                    log!(
-                        error,
-                        "failed to flush remaining data to child process stdin."
-                    );
-                }
+    let mut output = CapturedOutput {
+        stdout: Vec::new(),
+        total_bytes: 0,
+        matches: None,
+    };
+    let mut early_abort = None;
+
+    if let Some(mut child_stdout) = child.stdout.take() {
+        early_abort = match stream_output(
+            &mut child_stdout,
+            &mut output,
+            comparator.as_mut(),
+            output_limit,
+            retained_bytes,
+        ) {
+            Ok(early_abort) => early_abort,
+            Err(err) => {
+                let _ = child.kill();
+                return Err(io_context("Failed to read binary output".to_string())(err));
+            }
+        };
+    }
+
+    if early_abort.is_some() {
+        // Already exited on its own if this fails.
+        let _ = child.kill();
+    }
+
+    let Exit {
+        status,
+        timed_out,
+        peak_memory,
+    } = limits::wait(child, watchdog).map_err(io_context(
+        "Failed to wait for binary execution".to_string(),
+    ))?;
+
+    let elapsed = now.elapsed();
+
+    if timed_out {
+        return Ok(ExecutionStatus::TimeLimitExceeded {
+            time_elapsed: elapsed,
+        });
+    }
+    if let (Some(memory_limit), Some(peak_memory)) = (memory_limit, peak_memory) {
+        if peak_memory > memory_limit {
+            return Ok(ExecutionStatus::MemoryLimitExceeded {
+                peak_memory,
+                time_elapsed: elapsed,
             });
         }
     }
-    let output = child.wait_with_output().map_err(|err| {
-        io::Error::new(
-            io::ErrorKind::Other,
-            format!("Failed to wait for binary execution: {err}"),
-        )
-    })?;
 
-    let elapsed = now.elapsed();
+    if let (Some(io_files), Some(working_dir), true) = (io_files, &working_dir, status.success()) {
+        let output_path = working_dir.path().join(&io_files.output);
+
+        // A missing output file is judged like empty output.
+        if output_path.is_file() {
+            let mut output_file = fs::File::open(&output_path)
+                .map_err(io_context(format!("Failed to read {output_path:?}")))?;
+
+            early_abort = stream_output(
+                &mut output_file,
+                &mut output,
+                comparator.as_mut(),
+                output_limit,
+                retained_bytes,
+            )
+            .map_err(io_context(format!("Failed to read {output_path:?}")))?;
+        }
+    }
+
+    output.matches = comparator
+        .as_mut()
+        .map(OutputComparator::matches)
+        .transpose()
+        .map_err(io_context("Failed to read expected output".to_string()))?;
 
-    if output.status.success() {
-        Ok(ExecutionStatus::Successful {
+    match early_abort {
+        Some(EarlyAbort::OutputLimitExceeded) => Ok(ExecutionStatus::OutputLimitExceeded {
             output,
             time_elapsed: elapsed,
-        })
-    } else {
-        Ok(ExecutionStatus::Failed(format!(
-            "Binary execution failed with status: {:?}",
-            output.status
-        )))
+        }),
+        Some(EarlyAbort::OutputDiverged) => Ok(ExecutionStatus::Successful {
+            output,
+            time_elapsed: elapsed,
+        }),
+        None if status.success() => Ok(ExecutionStatus::Successful {
+            output,
+            time_elapsed: elapsed,
+        }),
+        None => Ok(ExecutionStatus::Failed {
+            message: format!("Binary execution failed with status: {:?}", status),
+            output,
+            time_elapsed: elapsed,
+        }),
+    }
+}
+
+/// Reads output into `output` and `comparator` until it ends, or until the
+/// run should be stopped because it printed too much or diverged. Only the
+/// first `retained_bytes` are kept in `output`.
+fn stream_output(
+    reader: &mut impl Read,
+    output: &mut CapturedOutput,
+    mut comparator: Option<&mut OutputComparator>,
+    output_limit: usize,
+    retained_bytes: usize,
+) -> io::Result<Option<EarlyAbort>> {
+    let mut buffer = vec![0; 64 * 1024];
+
+    loop {
+        let read = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(None),
+            Ok(read) => read,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        };
+        let chunk = &buffer[..read];
+        output.total_bytes += read;
+
+        let retained = (retained_bytes - output.stdout.len()).min(read);
+        output.stdout.extend_from_slice(&chunk[..retained]);
+
+        if output.total_bytes > output_limit {
+            return Ok(Some(EarlyAbort::OutputLimitExceeded));
+        }
+        if let Some(comparator) = comparator.as_mut() {
+            if !comparator.feed(chunk)? {
+                return Ok(Some(EarlyAbort::OutputDiverged));
+            }
+        }
     }
 }
@@ -0,0 +1,209 @@
+use super::core::{capture_output, execute_binary, ExecutionStatus, ExecutionTarget};
+use super::test::{
+    displayed_output, effective_output_limit, selected_cases, TestCase, TestOutcome,
+};
+use super::{cache_key, prepare_binary, ExecutionInput, RunError};
+use crate::cache_file::{get_config, get_file, Verdict};
+use crate::log;
+use crate::selector_evaluator::evaluate;
+use crate::test_file::TestInput;
+use crate::utils::{padded_string, SHOWN_BYTES};
+
+use colored::Colorize;
+use crossterm::terminal;
+use std::path::Path;
+
+/// How a run of one of the solutions ended.
+enum Outcome {
+    Output(Vec<u8>),
+    Failed(Verdict),
+}
+
+impl Outcome {
+    fn to_display_string(&self) -> String {
+        match self {
+            Outcome::Output(output) => String::from_utf8_lossy(output).into_owned(),
+            Outcome::Failed(verdict) => format!("<{verdict}>"),
+        }
+    }
+}
+
+fn run_outcome(status: ExecutionStatus, src_path: &Path) -> Result<Outcome, RunError> {
+    match status {
+        ExecutionStatus::Successful { output, .. } => Ok(Outcome::Output(displayed_output(output))),
+        ExecutionStatus::OutputLimitExceeded { .. } => {
+            Ok(Outcome::Failed(Verdict::OutputLimitExceeded))
+        }
+        ExecutionStatus::TimeLimitExceeded { .. } => {
+            Ok(Outcome::Failed(Verdict::TimeLimitExceeded))
+        }
+        ExecutionStatus::MemoryLimitExceeded { .. } => {
+            Ok(Outcome::Failed(Verdict::MemoryLimitExceeded))
+        }
+        ExecutionStatus::Failed { .. } => Ok(Outcome::Failed(Verdict::RuntimeError)),
+        ExecutionStatus::NeedRecompilation => Err(RunError::Config(format!(
+            "Binary of {src_path:?} is missing right after compiling it."
+        ))),
+    }
+}
+
+/// Runs `case` with both solutions and returns their outcomes when they
+/// disagree. The output of the first one is the expected output of the
+/// second, so they are compared the way tests are judged.
+fn compare_case(
+    target: &ExecutionTarget,
+    other_target: &ExecutionTarget,
+    case: &TestCase,
+) -> Result<Option<(Outcome, Outcome)>, RunError> {
+    let input = || ExecutionInput::CustomInput(case.input.clone());
+
+    match capture_output(target, input(), &case.run_args)? {
+        ExecutionStatus::Successful { output, .. } => {
+            let reference = output.stdout;
+            let expected_output = TestInput::from(reference.clone());
+
+            match execute_binary(
+                other_target,
+                input(),
+                &case.run_args,
+                Some(&expected_output),
+            )? {
+                ExecutionStatus::Successful { output, .. } if output.matches == Some(true) => {
+                    Ok(None)
+                }
+                status => Ok(Some((
+                    Outcome::Output(reference),
+                    run_outcome(status, other_target.src_path)?,
+                ))),
+            }
+        }
+        status => {
+            let outcome = run_outcome(status, target.src_path)?;
+            let other_outcome = run_outcome(
+                execute_binary(other_target, input(), &case.run_args, None)?,
+                other_target.src_path,
+            )?;
+
+            // Failing the same way is agreeing.
+            match (&outcome, &other_outcome) {
+                (Outcome::Failed(verdict), Outcome::Failed(other_verdict))
+                    if verdict == other_verdict =>
+                {
+                    Ok(None)
+                }
+                _ => Ok(Some((outcome, other_outcome))),
+            }
+        }
+    }
+}
+
+pub fn diff_run(
+    src_path: &Path,
+    other_src_path: &Path,
+    expression: Option<&str>,
+    profile: Option<&str>,
+    output_limit: Option<usize>,
+) -> Result<TestOutcome, RunError> {
+    let config = get_config()?;
+
+    if !config.binary_dir_path.is_dir() {
+        return Err(RunError::Config("Binary path not found.".to_string()));
+    }
+
+    let filename = cache_key(src_path)?;
+    let other_filename = cache_key(other_src_path)?;
+
+    let Some(file_cache) = get_file(filename)?.filter(|file_cache| !file_cache.tests.is_empty())
+    else {
+        log!(info, "No test found.");
+        return Ok(TestOutcome::Passed);
+    };
+
+    let expression = expression.unwrap_or("*");
+    let selection = evaluate(expression, file_cache.tests.len())?;
+    let cases = selected_cases(&file_cache.tests, &selection)?;
+
+    if cases.is_empty() {
+        return Err(RunError::Usage(format!(
+            "No test matches \"{expression}\"."
+        )));
+    }
+
+    for path in [src_path, other_src_path] {
+        prepare_binary(&config, path, profile, false)?;
+    }
+
+    // Both solutions run the way the tests of the first one are judged.
+    let target = ExecutionTarget {
+        config: &config,
+        src_path,
+        profile,
+        output_limit: effective_output_limit(&file_cache, output_limit),
+        time_limit: file_cache.time_limit,
+        memory_limit: file_cache.memory_limit,
+        io_files: file_cache.io_files.as_ref(),
+    };
+    let other_target = ExecutionTarget {
+        src_path: other_src_path,
+        ..target
+    };
+
+    let cols = terminal::size()?.0 as usize;
+    let rows = 15;
+    let mut differing = 0;
+
+    for case in &cases {
+        let Some((outcome, other_outcome)) = compare_case(&target, &other_target, case)? else {
+            println!(
+                "* ✅ {}{} {}.",
+                "Test #".purple(),
+                case.selector.yellow(),
+                "same output".green()
+            );
+            continue;
+        };
+        differing += 1;
+
+        let input = case.input.head(SHOWN_BYTES)?;
+        let input = String::from_utf8_lossy(&input);
+        let (output, other_output) = (
+            outcome.to_display_string(),
+            other_outcome.to_display_string(),
+        );
+
+        println!(
+            "* ❌ {}{} {}.\n{}\n{}\n{}\n{}\n{}\n{}",
+            "Test #".purple(),
+            case.selector.yellow(),
+            "outputs differ".red(),
+            "Input:".bold(),
+            padded_string(&input, cols, rows, input.lines().count() == 1).blue(),
+            format!("{filename}:").bold(),
+            padded_string(&output, cols, rows, output.lines().count() == 1).green(),
+            format!("{other_filename}:").bold(),
+            padded_string(&other_output, cols, rows, other_output.lines().count() == 1).red(),
+        );
+    }
+
+    if differing == 0 {
+        println!(
+            "{}",
+            format!(
+                "* ✅ {filename} and {other_filename} agree on all {} tests.",
+                cases.len()
+            )
+            .green()
+        );
+    } else {
+        println!(
+            "{}",
+            format!(
+                "* ❌ {filename} and {other_filename} differ on {differing} of {} tests.",
+                cases.len()
+            )
+            .red()
+        );
+    }
+
+    Ok(TestOutcome::from_all_passed(differing == 0))
+}
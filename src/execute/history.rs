@@ -0,0 +1,250 @@
+use super::{cache_key, RunError};
+use crate::cache_file::Verdict;
+use crate::history_file::{get_history, HistoryRun, TestRecord};
+use crate::log;
+use crate::utils::sha256_digest;
+
+use colored::{ColoredString, Colorize};
+use data_encoding::HEXUPPER;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Consecutive runs made from the same source.
+struct Revision {
+    source_hash: String,
+    runs: usize,
+    last_timestamp: u64,
+    /// Latest record of every test run from this source.
+    results: BTreeMap<String, TestRecord>,
+}
+
+impl Revision {
+    fn passed(&self) -> usize {
+        self.results
+            .values()
+            .filter(|record| record.verdict.is_passed())
+            .count()
+    }
+
+    fn total_time(&self) -> Duration {
+        self.results
+            .values()
+            .map(|record| record.time_elapsed)
+            .sum()
+    }
+
+    fn pass_rate(&self) -> f64 {
+        self.passed() as f64 / self.results.len().max(1) as f64
+    }
+}
+
+fn group_revisions(runs: Vec<HistoryRun>) -> Vec<Revision> {
+    let mut revisions: Vec<Revision> = Vec::new();
+
+    for run in runs {
+        match revisions.last_mut() {
+            Some(revision) if revision.source_hash == run.source_hash => {
+                revision.runs += 1;
+                revision.last_timestamp = run.timestamp;
+                revision.results.extend(run.results);
+            }
+            _ => revisions.push(Revision {
+                source_hash: run.source_hash,
+                runs: 1,
+                last_timestamp: run.timestamp,
+                results: run.results,
+            }),
+        }
+    }
+
+    revisions
+}
+
+/// Orders selectors like `2` before `10` and `1.2` after `1`.
+fn selector_order(selector: &str) -> Vec<usize> {
+    selector
+        .split('.')
+        .map(|part| part.parse().unwrap_or(usize::MAX))
+        .collect()
+}
+
+/// Total time of the tests passed in both revisions, so that added or failing
+/// tests don't count as slowdowns.
+fn common_times(previous: &Revision, current: &Revision) -> (Duration, Duration) {
+    current
+        .results
+        .iter()
+        .filter_map(|(selector, record)| {
+            let previous_record = previous.results.get(selector)?;
+            (record.verdict.is_passed() && previous_record.verdict.is_passed())
+                .then_some((previous_record.time_elapsed, record.time_elapsed))
+        })
+        .fold(
+            (Duration::ZERO, Duration::ZERO),
+            |(previous_total, current_total), (previous_time, current_time)| {
+                (previous_total + previous_time, current_total + current_time)
+            },
+        )
+}
+
+fn change_percent(previous: Duration, current: Duration) -> Option<f64> {
+    if previous.is_zero() {
+        return None;
+    }
+
+    Some((current.as_secs_f64() - previous.as_secs_f64()) / previous.as_secs_f64() * 100.0)
+}
+
+fn change_string(change: Option<f64>, threshold: f64) -> ColoredString {
+    match change {
+        None => "-".normal(),
+        Some(change) if change > threshold => format!("{change:+.1}%").red(),
+        Some(change) if change < -threshold => format!("{change:+.1}%").green(),
+        Some(change) => format!("{change:+.1}%").normal(),
+    }
+}
+
+fn age_string(timestamp: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    let age = now.saturating_sub(timestamp);
+
+    match age {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", age / 60),
+        3600..86400 => format!("{}h ago", age / 3600),
+        _ => format!("{}d ago", age / 86400),
+    }
+}
+
+/// Lists the tests that started failing or got slower than `threshold`
+/// percent between the two revisions.
+fn print_regressions(previous: &Revision, current: &Revision, threshold: f64) {
+    let mut selectors: Vec<&String> = current.results.keys().collect();
+    selectors.sort_by_key(|selector| selector_order(selector));
+
+    let mut regressions = 0;
+    for selector in selectors {
+        let (Some(previous_record), Some(record)) = (
+            previous.results.get(selector),
+            current.results.get(selector),
+        ) else {
+            continue;
+        };
+
+        match (previous_record.verdict, record.verdict) {
+            (Verdict::Passed, Verdict::Passed) => {
+                let Some(change) =
+                    change_percent(previous_record.time_elapsed, record.time_elapsed)
+                else {
+                    continue;
+                };
+
+                if change > threshold {
+                    println!(
+                        "  Test #{selector}: {:.1?} -> {:.1?} ({})",
+                        previous_record.time_elapsed,
+                        record.time_elapsed,
+                        format!("{change:+.1}%").red()
+                    );
+                    regressions += 1;
+                }
+            }
+            (Verdict::Passed, verdict) => {
+                println!(
+                    "  Test #{selector}: {}",
+                    format!("now fails, {verdict}").red()
+                );
+                regressions += 1;
+            }
+            _ => {}
+        }
+    }
+
+    if regressions == 0 {
+        log!(success, "No regression since the previous revision.");
+    }
+}
+
+pub fn history(src_path: &Path, profile: Option<&str>, threshold: f64) -> Result<(), RunError> {
+    let filename = cache_key(src_path)?;
+
+    let runs: Vec<HistoryRun> = get_history()?
+        .files
+        .remove(filename)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|run| run.profile.as_deref() == profile)
+        .collect();
+
+    if runs.is_empty() {
+        log!(info, "No test run recorded for {src_path:?}.");
+        return Ok(());
+    }
+
+    let current_hash =
+        HEXUPPER.encode(sha256_digest(io::BufReader::new(fs::File::open(src_path)?))?.as_ref());
+    let revisions = group_revisions(runs);
+
+    println!(
+        "{}",
+        format!(
+            "{} revision{} of {filename}.",
+            revisions.len(),
+            if revisions.len() > 1 { "s" } else { "" }
+        )
+        .blue()
+    );
+    println!(
+        "{:<4} {:<10} {:<10} {:<6} {:<8} {:<12} {:<8}",
+        "No.".cyan(),
+        "Revision".cyan(),
+        "Last run".cyan(),
+        "Runs".cyan(),
+        "Passed".cyan(),
+        "Total time".cyan(),
+        "Change".cyan()
+    );
+
+    for (index, revision) in revisions.iter().enumerate() {
+        let previous = index.checked_sub(1).map(|index| &revisions[index]);
+
+        let short_hash = &revision.source_hash[..8.min(revision.source_hash.len())];
+        let passed = format!("{}/{}", revision.passed(), revision.results.len());
+        let change = previous.and_then(|previous| {
+            let (previous_time, current_time) = common_times(previous, revision);
+            change_percent(previous_time, current_time)
+        });
+
+        println!(
+            "{:<4} {:<10} {:<10} {:<6} {:<8} {:<12} {}",
+            (index + 1).to_string().cyan(),
+            if revision.source_hash == current_hash {
+                format!("{short_hash}*").green()
+            } else {
+                short_hash.normal()
+            },
+            age_string(revision.last_timestamp),
+            revision.runs,
+            match previous {
+                Some(previous) if revision.pass_rate() < previous.pass_rate() => passed.red(),
+                _ if revision.passed() == revision.results.len() => passed.green(),
+                _ => passed.yellow(),
+            },
+            format!("{:.1?}", revision.total_time()),
+            change_string(change, threshold)
+        );
+    }
+
+    if let [.., previous, current] = revisions.as_slice() {
+        println!();
+        log!(info, "Latest revision compared to the previous one:");
+        print_regressions(previous, current, threshold);
+    }
+
+    Ok(())
+}
@@ -1,110 +1,159 @@
 use crate::cache_file::{
-    get_config, get_file, put_config, put_file, FileCache, Files, Test, DEFAULT_CACHE_FILE,
-    DEFUALT_BIN_DIR,
+    get_config, get_file, put_config, put_file, to_project_path, FileCache, Files, LanguageProfile,
+    RunArgs, Test, DEFAULT_CACHE_FILE, DEFAULT_PROFILE, DEFUALT_BIN_DIR,
 };
 use crate::log;
+use crate::sandbox;
+use crate::selector_evaluator::RangeEvaluationError;
 use crate::utils::limited_string;
 use crate::utils::sha256_digest;
 
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
 use crossterm::terminal;
 use data_encoding::HEXUPPER;
-use std::cmp;
 use std::collections::HashMap;
 use std::fs;
 use std::io::{self, Write};
 use std::path::Path;
 use thiserror::Error;
 
+pub mod bench;
+pub mod bless;
+pub mod bundle;
 pub mod cache;
 pub mod core;
+pub mod diff_run;
+pub mod history;
 pub mod test;
 
-use core::{execute_binary, recompile_binary, ExecutionInput, ExecutionStatus};
+use core::{
+    current_build_key, execute_binary, recompile_binary, ExecutionInput, ExecutionStatus,
+    ExecutionTarget,
+};
+use test::TestOutcome;
 
 #[derive(Debug, Error)]
 pub enum RunError {
     #[error("I/O Error: {0}")]
     Io(#[from] io::Error),
 
+    #[error("Config Error: {0}")]
+    Config(String),
+
     #[error("Compilation Error: {0}")]
     CompilationError(String),
 
-    #[error("Other Error: {0}")]
-    Other(String),
-}
+    #[error("Parse Error: {0}")]
+    Parse(String),
 
-pub fn run(path: &Path, force_recompile: bool) -> Result<(), RunError> {
-    assert!(path.exists());
+    #[error("Selector Error: {0}")]
+    Selector(#[from] RangeEvaluationError),
 
-    let filename = path.file_name().unwrap().to_str().unwrap();
+    #[error("Usage Error: {0}")]
+    Usage(String),
+}
 
-    let target_file = fs::File::open(path)?;
-    let target_reader = io::BufReader::new(target_file);
-    let target_hashed = HEXUPPER.encode(sha256_digest(target_reader)?.as_ref());
+impl RunError {
+    /// Process exit code erunner terminates with when this error reaches `main`.
+    /// `1` is left to failed tests.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunError::CompilationError(_) => 2,
+            RunError::Config(_) | RunError::Usage(_) | RunError::Selector(_) => 3,
+            RunError::Parse(_) => 4,
+            RunError::Io(_) => 5,
+        }
+    }
+}
 
-    let config = get_config()?; // Assume get_config returns io::Result
+/// Name `path` is tracked under in the cache.
+pub fn cache_key(path: &Path) -> Result<&str, RunError> {
+    path.file_name()
+        .and_then(|filename| filename.to_str())
+        .ok_or_else(|| RunError::Usage(format!("{path:?} is not a valid source filename.")))
+}
 
-    match get_file(filename) {
-        Ok(Some(file_cache)) if file_cache.source_hash == target_hashed && !force_recompile => {
+/// Recompiles `path` with `profile` unless the cached binary was built from
+/// the current source, compile command and toolchain, and returns the
+/// up-to-date cache entry of the file.
+pub fn prepare_binary(
+    config: &Files,
+    path: &Path,
+    profile: Option<&str>,
+    force_recompile: bool,
+) -> Result<FileCache, RunError> {
+    let filename = cache_key(path)?;
+
+    let build_key = current_build_key(config, path, profile)?;
+    let file_cache = get_file(filename).ok().flatten();
+
+    let stale_reasons = match file_cache
+        .as_ref()
+        .and_then(|file_cache| file_cache.build_key(profile))
+    {
+        Some(cached_build_key) => cached_build_key.stale_reasons(&build_key),
+        None => vec!["not built yet"],
+    };
+
+    if let (Some(file_cache), true) = (&file_cache, stale_reasons.is_empty()) {
+        if !force_recompile {
             log!(info, "Cache hit for {path:?}. Skipping recompilation.");
+            return Ok(file_cache.clone());
         }
-        Ok(Some(file_cache)) => {
-            if force_recompile {
-                log!(
-                    warn,
-                    "Force recompilation Source file {path:?}. Recompiling..."
-                );
-            } else {
-                log!(warn, "Source file {path:?} has changed. Recompiling...");
-            }
+    }
 
-            recompile_binary(path).map_err(RunError::CompilationError)?;
+    if force_recompile {
+        log!(
+            warn,
+            "Force recompilation Source file {path:?}. Recompiling..."
+        );
+    } else {
+        log!(
+            warn,
+            "Binary for {path:?} is stale ({}). Recompiling...",
+            stale_reasons.join(", ")
+        );
+    }
 
-            log!(
-                success,
-                "Recompilation succeeded. Binary for {path:?} is ready."
-            );
+    recompile_binary(path, profile)?;
 
-            let file_cache = FileCache {
-                source_hash: target_hashed,
-                tests: file_cache.tests,
-            };
+    log!(
+        success,
+        "Compilation succeeded. Binary for {path:?} is ready."
+    );
 
-            put_file(filename, file_cache.clone())?;
-        }
-        _ => {
-            if force_recompile {
-                log!(
-                    warn,
-                    "Force recompilation Source file {path:?}. Recompiling..."
-                );
-            } else {
-                log!(warn, "Source file {path:?} has changed. Recompiling...");
-            }
+    let mut file_cache =
+        file_cache.unwrap_or_else(|| FileCache::new(build_key.source_hash.clone(), Vec::new()));
+    file_cache.set_build_key(profile, build_key);
 
-            recompile_binary(path).map_err(RunError::CompilationError)?;
+    put_file(filename, file_cache.clone())?;
 
-            log!(
-                success,
-                "Compilation succeeded. Binary for {path:?} is ready."
-            );
+    Ok(file_cache)
+}
 
-            let file_cache = FileCache {
-                source_hash: target_hashed,
-                tests: Vec::new(),
-            };
+pub fn run(
+    path: &Path,
+    profile: Option<&str>,
+    force_recompile: bool,
+    run_args: &RunArgs,
+) -> Result<TestOutcome, RunError> {
+    let config = get_config()?; // Assume get_config returns io::Result
 
-            put_file(filename, file_cache.clone())?;
-        }
-    }
+    prepare_binary(&config, path, profile, force_recompile)?;
+
+    let target = ExecutionTarget {
+        config: &config,
+        src_path: path,
+        profile,
+        // Output goes straight to the terminal and is never captured.
+        output_limit: core::DEFAULT_OUTPUT_LIMIT,
+        time_limit: None,
+        memory_limit: None,
+        io_files: None,
+    };
 
     loop {
-        match execute_binary(
-            &config.binary_dir_path,
-            filename,
-            ExecutionInput::InheritFromTerminal,
-        )? {
+        match execute_binary(&target, ExecutionInput::InheritFromTerminal, run_args, None)? {
             ExecutionStatus::Successful {
                 output: _,
                 time_elapsed,
@@ -113,16 +162,26 @@ pub fn run(path: &Path, force_recompile: bool) -> Result<(), RunError> {
                     success,
                     "Execution of {path:?} completed successfully in {time_elapsed:?}."
                 );
-                return Ok(());
+                return Ok(TestOutcome::Passed);
             }
-            ExecutionStatus::Failed(err) => {
-                log!(error, "Execution of {path:?} failed due to error: {err}.");
-                return Ok(());
+            ExecutionStatus::Failed { message, .. } => {
+                log!(
+                    error,
+                    "Execution of {path:?} failed due to error: {message}."
+                );
+                return Ok(TestOutcome::Failed);
+            }
+            ExecutionStatus::OutputLimitExceeded { .. }
+            | ExecutionStatus::TimeLimitExceeded { .. }
+            | ExecutionStatus::MemoryLimitExceeded { .. } => {
+                return Err(RunError::Config(format!(
+                    "Execution of {path:?} exceeded a limit, though runs from the terminal have none."
+                )));
             }
             ExecutionStatus::NeedRecompilation => {
                 log!(warn, "Execution failed, recompilation needed for {path:?}.");
 
-                recompile_binary(path).map_err(RunError::CompilationError)?;
+                recompile_binary(path, profile)?;
 
                 log!(
                     success,
@@ -133,6 +192,35 @@ pub fn run(path: &Path, force_recompile: bool) -> Result<(), RunError> {
     }
 }
 
+pub fn set_sandbox(enabled: bool) -> io::Result<()> {
+    let config = get_config()?;
+    put_config(Files {
+        sandbox: enabled,
+        ..config
+    })?;
+
+    if !enabled {
+        log!(
+            success,
+            "Sandbox disabled, solutions run with your privileges."
+        );
+        return Ok(());
+    }
+
+    log!(
+        success,
+        "Sandbox enabled, solutions run in a temporary directory with a cleared environment."
+    );
+    if !sandbox::network_isolation_supported() {
+        log!(
+            warn,
+            "Network namespaces aren't available here, solutions keep network access."
+        );
+    }
+
+    Ok(())
+}
+
 pub fn check_initialized(current_path: &Path) -> bool {
     current_path.join(DEFAULT_CACHE_FILE).is_file()
 }
@@ -148,7 +236,7 @@ pub fn initialize(current_path: &Path) -> io::Result<()> {
                 question,
                 "Specify the location for the compiled binary (Press Enter for default location): "
             );
-            io::stdout().flush().unwrap();
+            io::stdout().flush()?;
 
             let mut location = String::new();
             io::stdin().read_line(&mut location)?;
@@ -175,29 +263,62 @@ pub fn initialize(current_path: &Path) -> io::Result<()> {
             );
         }
 
-        let languages_config: HashMap<String, String> = HashMap::from([
+        let languages_config: HashMap<String, LanguageProfile> = HashMap::from([
             (
                 "cpp".to_string(),
-                "g++ $(FILE) -o $(BIN_DIR)/$(FILENAME).$(EXE_EXT) --std=c++20".to_string(),
+                LanguageProfile::compiled("g++ $(FILE) -o $(BINARY) --std=c++20"),
+            ),
+            (
+                "cpp.release".to_string(),
+                LanguageProfile::compiled("g++ $(FILE) -o $(BINARY) --std=c++20 -O2"),
+            ),
+            (
+                "cpp.debug".to_string(),
+                LanguageProfile::compiled("g++ $(FILE) -o $(BINARY) --std=c++20 -g -O0"),
+            ),
+            (
+                "cpp.asan".to_string(),
+                LanguageProfile::compiled(
+                    "g++ $(FILE) -o $(BINARY) --std=c++20 -g -fsanitize=address,undefined",
+                ),
             ),
             (
                 "c".to_string(),
-                "gcc $(FILE) -o $(BIN_DIR)/$(FILENAME).$(EXE_EXT)".to_string(),
+                LanguageProfile::compiled("gcc $(FILE) -o $(BINARY)"),
+            ),
+            (
+                "rs".to_string(),
+                LanguageProfile::compiled("rustc $(FILE) -o $(BINARY)"),
+            ),
+            (
+                "java".to_string(),
+                LanguageProfile::with_run(
+                    "javac -d $(BINARY) $(FILE)",
+                    "java -cp $(BINARY) $(FILESTEM)",
+                ),
             ),
             (
                 "py".to_string(),
-                "sh -c 'echo \"#!$(which python3)\" > $(BIN_DIR)/$(FILENAME).$(EXE_EXT); cat $(FILE) >> $(BIN_DIR)/$(FILENAME).$(EXE_EXT); chmod +x $(BIN_DIR)/$(FILENAME).$(EXE_EXT)'".to_string()
+                LanguageProfile::interpreted("python3 $(FILE)"),
             ),
             (
-                "rs".to_string(),
-                "rustc $(FILE) -o $(BIN_DIR)/$(FILENAME).$(EXE_EXT)".to_string()
-            )
+                "js".to_string(),
+                LanguageProfile::interpreted("node $(FILE)"),
+            ),
+            (
+                "rb".to_string(),
+                LanguageProfile::interpreted("ruby $(FILE)"),
+            ),
         ]);
 
+        // Stored relative to the project, so it keeps working once the project moves.
+        let binary_dir_path = to_project_path(&fs::canonicalize(&binary_dir_path)?)?;
+
         let files = Files {
-            binary_dir_path: binary_dir_path.clone(),
+            binary_dir_path,
             files: HashMap::new(),
             languages_config,
+            sandbox: false,
         };
 
         let file = fs::File::create(current_path.join(DEFAULT_CACHE_FILE))?;
@@ -221,14 +342,8 @@ fn test_types_string(tests: &[Test]) -> String {
     tests
         .iter()
         .map(|test| match test {
-            Test::StringTest {
-                input: _,
-                expected_output: _,
-            } => "S".green(),
-            Test::RefTest {
-                input: _,
-                expected_output: _,
-            } => "R".yellow(),
+            Test::StringTest { .. } => "S".green(),
+            Test::RefTest { .. } => "R".yellow(),
         })
         .fold(String::new(), |mut acc, colored_string| {
             acc.push_str(&colored_string.to_string());
@@ -236,6 +351,35 @@ fn test_types_string(tests: &[Test]) -> String {
         })
 }
 
+/// Summarises which recorded binaries of a file are stale, and why.
+fn binary_status_string(config: &Files, path: &Path, file_cache: &FileCache) -> ColoredString {
+    if file_cache.builds.is_empty() {
+        return "not built".yellow();
+    }
+
+    let mut stale_builds: Vec<String> = file_cache
+        .builds
+        .iter()
+        .filter_map(|(profile_name, build_key)| {
+            let profile = (profile_name != DEFAULT_PROFILE).then_some(profile_name.as_str());
+
+            let reasons = match current_build_key(config, path, profile) {
+                Ok(current_build_key) => build_key.stale_reasons(&current_build_key),
+                Err(_) => vec!["unknown"],
+            };
+
+            (!reasons.is_empty()).then(|| format!("{}:{}", profile_name, reasons.join("+")))
+        })
+        .collect();
+
+    if stale_builds.is_empty() {
+        return "fresh".green();
+    }
+
+    stale_builds.sort();
+    limited_string(&stale_builds.join(" "), 24, 1, false).yellow()
+}
+
 pub fn status() -> io::Result<()> {
     let config = get_config()?;
 
@@ -247,15 +391,16 @@ pub fn status() -> io::Result<()> {
 
     println!("{}", format!("Tracked {} files.", tracked_numbers).blue());
 
-    let hash_width = cmp::min(cmp::max(0, terminal::size()?.0 as i32) - 55, 65) as usize;
+    let hash_width = (terminal::size()?.0 as i32 - 80).clamp(10, 65) as usize;
 
     // Print table headers with colors
     println!(
-        "{:<4} {:<20} {:<6} {:<hash_width$} {:<10} {:<10}",
+        "{:<4} {:<20} {:<6} {:<hash_width$} {:<24} {:<10} {:<10}",
         "No.".cyan(),
         "Filename".cyan(),
         "Exists".cyan(),
         "  Hash".cyan(),
+        "  Binary".cyan(),
         "Tests".cyan(),
         "Types".cyan()
     );
@@ -273,11 +418,11 @@ pub fn status() -> io::Result<()> {
         };
 
         println!(
-            "{:<4} {:<20} {:<6}   {:<hash_width$} {:<10} [{}]",
+            "{:<4} {:<20} {:<6}   {:<hash_width$} {:<24} {:<10} [{}]",
             (index + 1).to_string().cyan(),
             if !file_exists {
                 limited_string(filename, 20, 1, false).red().strikethrough()
-            } else if file_cache.source_hash == target_hashed.clone().unwrap() {
+            } else if target_hashed.as_ref() == Some(&file_cache.source_hash) {
                 limited_string(filename, 20, 1, false).green()
             } else {
                 limited_string(filename, 20, 1, false).yellow()
@@ -291,11 +436,16 @@ pub fn status() -> io::Result<()> {
                 limited_string(&file_cache.source_hash, hash_width, 1, false)
                     .red()
                     .strikethrough()
-            } else if file_cache.source_hash == target_hashed.unwrap() {
+            } else if target_hashed.as_ref() == Some(&file_cache.source_hash) {
                 limited_string(&file_cache.source_hash, hash_width, 1, false).blue()
             } else {
                 limited_string(&file_cache.source_hash, hash_width, 1, false).yellow()
             },
+            if file_exists {
+                binary_status_string(&config, &fs::canonicalize(filename)?, file_cache)
+            } else {
+                "-".red()
+            },
             file_cache.tests.len().to_string().magenta(),
             test_types_string(file_cache.tests.as_slice())
         );
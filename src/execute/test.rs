@@ -1,308 +1,327 @@
-use super::core::ExecutionStatus;
-use super::RunError;
-use crate::cache_file::{get_config, put_file, Test};
-use crate::cache_file::{get_file, FileCache};
+use super::core::{
+    current_build_key, CapturedOutput, ExecutionStatus, ExecutionTarget, DEFAULT_OUTPUT_LIMIT,
+};
+use super::{cache_key, prepare_binary, RunError};
+use crate::cache_file::{get_config, put_file, resolve_project_path, to_project_path, Test};
+use crate::cache_file::{get_file, FileCache, Files, IoFiles, RunArgs, TestLabels, Verdict};
 use crate::execute::{core::execute_binary, recompile_binary, ExecutionInput};
+use crate::history_file::{append_run, HistoryRun, TestRecord};
 use crate::log;
-use crate::selector_evaluator::evaluate;
-use crate::test_file::{merge_test_file, read_test_file, SimpleTest};
-use crate::utils::{padded_string, sha256_digest};
+use crate::section_file::{write_section_file, SECTION_FILE_EXTENSION};
+use crate::selector_evaluator::{evaluate, Selection, TestPosition};
+use crate::test_file::{merge_test_file, read_test_file, SimpleTest, TestInput};
+use crate::utils::{limited_string, padded_string, sha256_digest, SHOWN_BYTES};
 use colored::Colorize;
 use crossterm::terminal;
 use data_encoding::HEXUPPER;
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::fs::File;
-use std::io::{self, BufReader};
-use std::ops::RangeInclusive;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 pub fn run_at(
     src_path: &Path,
     expression: &str,
+    profile: Option<&str>,
     force_recompile: bool,
     show_full: bool,
-) -> Result<(), RunError> {
-    assert!(src_path.exists());
-
-    let filename = src_path.file_name().unwrap().to_str().unwrap();
-
-    let target_file = File::open(src_path)?;
-    let target_reader = BufReader::new(target_file);
-    let target_hashed = HEXUPPER.encode(sha256_digest(target_reader)?.as_ref());
-
+    output_limit: Option<usize>,
+    first_failure: bool,
+) -> Result<TestOutcome, RunError> {
     let config = get_config()?;
 
     if !config.binary_dir_path.is_dir() {
-        return Err(RunError::Other("Binary path not found.".to_string()));
+        return Err(RunError::Config("Binary path not found.".to_string()));
     }
 
-    let file_cache = match get_file(filename) {
-        Ok(Some(file_cache)) if file_cache.source_hash == target_hashed && !force_recompile => {
-            log!(
-                info,
-                "Cache for {src_path:?} is matched, skip re-compiling.."
-            );
-            file_cache
-        }
-        Ok(Some(file_cache)) => {
-            log!(warn, "Re-compiling binary...");
-
-            recompile_binary(src_path).map_err(RunError::CompilationError)?;
-
-            let file_cache = FileCache {
-                source_hash: target_hashed,
-                tests: file_cache.tests,
-            };
-
-            put_file(filename, file_cache.clone())?;
-
-            file_cache
-        }
-        _ => {
-            log!(warn, "Re-compiling binary...");
-
-            recompile_binary(src_path).map_err(RunError::CompilationError)?;
+    let file_cache = prepare_binary(&config, src_path, profile, force_recompile)?;
 
-            let file_cache = FileCache {
-                source_hash: target_hashed,
-                tests: Vec::new(),
-            };
-
-            put_file(filename, file_cache.clone())?;
-
-            file_cache
-        }
+    let target = ExecutionTarget {
+        config: &config,
+        src_path,
+        profile,
+        output_limit: effective_output_limit(&file_cache, output_limit),
+        time_limit: file_cache.time_limit,
+        memory_limit: file_cache.memory_limit,
+        io_files: file_cache.io_files.as_ref(),
     };
 
     if file_cache.tests.is_empty() {
         log!(info, "No test found.");
-        return Ok(());
+        return Ok(TestOutcome::Passed);
     }
-    let range_tests = evaluate(expression)
-        .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+    let selection = evaluate(expression, file_cache.tests.len())?;
 
-    for range_test in range_tests {
-        let main_index = range_test.main_test;
+    let mut all_passed = true;
+    let mut selected_any = false;
+    let mut results = BTreeMap::new();
 
-        if (file_cache.tests.len() < main_index) || (main_index == 0) {
-            log!(
-                error,
-                "Test main index is not valid. It must be in the range of 1 to {}.",
-                file_cache.tests.len()
-            );
-            return Ok(());
+    for (index, test) in file_cache.tests.iter().enumerate() {
+        let main_index = index + 1;
+
+        if first_failure && !all_passed {
+            log!(info, "Stopped at the first failure.");
+            break;
         }
 
-        match &file_cache.tests[main_index - 1] {
+        if !selection.may_select(main_index, test.labels()) {
+            continue;
+        }
+
+        match test {
             Test::StringTest {
-                input: _,
-                expected_output: _,
+                input,
+                expected_output,
+                labels,
+                ..
             } => {
-                if let Ok(RunResult::SingleTest {
-                    status,
+                if !selection.selects(TestPosition::test(main_index), &[labels]) {
+                    continue;
+                }
+                selected_any = true;
+
+                if let RunResult::SingleTest {
+                    verdict,
                     time_elapsed,
                     output,
-                }) = run_core(
-                    &file_cache.tests[main_index - 1],
-                    src_path,
-                    filename,
-                    &config.binary_dir_path,
-                ) {
-                    let (input, expected_output) = match &file_cache.tests[main_index - 1] {
-                        Test::StringTest {
-                            input,
-                            expected_output,
-                        } => (input, expected_output),
-                        _ => unreachable!("Because it's a case of output single-test."),
-                    };
+                } = run_core(test, &target, None, first_failure)?
+                {
+                    let status = verdict.is_passed();
+                    all_passed &= status;
+                    results.insert(
+                        main_index.to_string(),
+                        TestRecord {
+                            verdict,
+                            time_elapsed,
+                        },
+                    );
 
-                    if status {
-                        println!(
-                            "* ✅ {}{} {} in {}.",
-                            "Test #".purple(),
-                            main_index.to_string().yellow(),
-                            "completed successfully".green(),
-                            format!("{:?}", time_elapsed).green().italic()
-                        );
-                    } else {
-                        let cols = terminal::size()?.0 as usize;
-                        let rows = 15;
-
-                        println!(
-                            "* ❌ {}{}. Taking: {}\n{}\n{}\n{}\n{}\n{}\n{}",
-                            "Test #".purple(),
-                            main_index.to_string().yellow(),
-                            format!("{:?}", time_elapsed).green().italic(),
-                            "Input:".bold(),
-                            padded_string(input, cols, rows, input.lines().count() == 1).blue(),
-                            "Output:".bold(),
-                            padded_string(&output, cols, rows, output.lines().count() == 1).red(),
-                            "Expected-output:".bold(),
-                            padded_string(
-                                expected_output,
-                                cols,
-                                rows,
-                                expected_output.lines().count() == 1
-                            )
-                            .green(),
-                        );
-                    }
+                    print_string_test_result(
+                        main_index,
+                        verdict,
+                        time_elapsed,
+                        input,
+                        expected_output.as_deref(),
+                        &output,
+                    )?;
                 }
             }
 
             Test::RefTest {
                 input,
                 expected_output,
-            } => match range_test.sub_tests {
-                Some(sub_tests) => {
-                    let Ok(ref_test_result) = _ref_test_run_core(
-                        src_path,
-                        _test_iterator(input, expected_output.as_ref()).map_err(RunError::Other)?,
-                        filename,
-                        &config.binary_dir_path,
-                        Some(&sub_tests),
-                    ) else {
-                        println!(
-                            "\r* ❌ Some test in {}.{} - {}.{}  is cooked",
-                            main_index,
-                            sub_tests.start(),
-                            main_index,
-                            sub_tests.end()
-                        );
-                        break;
-                    };
+                labels,
+                ..
+            } => {
+                // `$` sub-tests are only known once the file is read through.
+                let sub_test_count = match selection.needs_sub_test_count() {
+                    true => Some(
+                        _test_iterator(input, expected_output.as_ref())?
+                            .take_while(Result::is_ok)
+                            .count(),
+                    ),
+                    false => None,
+                };
+                let is_selected = |sub_index: usize, sub_test: &SimpleTest| {
+                    selection.selects(
+                        TestPosition::sub_test(main_index, sub_index, sub_test_count),
+                        &[labels, &sub_test.labels],
+                    )
+                };
 
-                    match ref_test_result {
-                        RunResult::RefTest {
-                            status: false,
-                            total_test: 0,
-                            passed_test: 0,
-                            detailed_status: _,
-                        } => {
-                            println!(
-                                "\r* ❌ Some test in {}.{} - {}.{}  isn't exists",
-                                main_index,
-                                sub_tests.start(),
-                                main_index,
-                                sub_tests.end()
-                            );
-                            break;
-                        }
-                        RunResult::RefTest {
-                            status,
-                            total_test,
-                            passed_test,
-                            detailed_status,
-                        } => {
-                            print_ref_testcases_detailed(
-                                _test_iterator(input, expected_output.as_ref())
-                                    .map_err(RunError::Other)?,
-                                detailed_status.as_slice(),
-                                show_full,
-                            )?;
-
-                            // printing fancy test.
-                            println!(
-                                "\r* {} [{}]{} {}{} {} in average of {}",
-                                if status { "✅" } else { "❌" },
-                                (passed_test as f32 / total_test as f32 * 100.0)
-                                    .to_string()
-                                    .yellow(),
-                                _ref_testcases_minimized(detailed_status.as_slice()),
-                                "Test #".purple(),
-                                if sub_tests.start() == sub_tests.end() {
-                                    format!(
-                                        "{}.{}",
-                                        main_index.to_string().yellow(),
-                                        sub_tests.start().to_string().yellow()
-                                    )
-                                } else {
-                                    format!(
-                                        "{}.{} - {}{}.{}",
-                                        main_index.to_string().yellow(),
-                                        sub_tests.start().to_string().yellow(),
-                                        "#".purple(),
-                                        main_index.to_string().yellow(),
-                                        sub_tests.end().to_string().yellow(),
-                                    )
-                                },
-                                if status {
-                                    "completed successfully".green()
-                                } else {
-                                    "failed".red()
-                                },
-                                format!(
-                                    "{:?}",
-                                    Duration::from_millis(
-                                        (detailed_status
-                                            .iter()
-                                            .map(|elm| elm.time_elapsed.as_millis())
-                                            .sum::<u128>()
-                                            / detailed_status.len() as u128)
-                                            as u64
-                                    )
-                                )
-                                .green()
-                                .italic()
-                            );
+                let ref_test_result =
+                    match run_core(test, &target, Some(&is_selected), first_failure) {
+                        Ok(ref_test_result) => ref_test_result,
+                        Err(error @ RunError::CompilationError(_)) => return Err(error),
+                        Err(error) => {
+                            log!(error, "{error}");
+                            selected_any = true;
+                            all_passed = false;
+                            continue;
                         }
-                        _ => unreachable!("tests in this loop will always be a ref test"),
-                    }
-                }
-                None => {
-                    if let Ok(RunResult::RefTest {
-                        status,
-                        total_test,
-                        passed_test,
-                        detailed_status,
-                    }) = _ref_test_run_core(
-                        src_path,
-                        _test_iterator(input, expected_output.as_ref()).map_err(RunError::Other)?,
-                        filename,
-                        &config.binary_dir_path,
-                        None,
-                    ) {
-                        print_ref_testcases_detailed(
-                            _test_iterator(input, expected_output.as_ref())
-                                .map_err(RunError::Other)?,
-                            detailed_status.as_slice(),
-                            show_full,
-                        )?;
-                        println!(
-                            "\r* {} [{}]{} {}{} {} in average of {}",
-                            if status { "✅" } else { "❌" },
-                            (passed_test as f32 / total_test as f32 * 100.0)
-                                .to_string()
-                                .yellow(),
-                            _ref_testcases_minimized(detailed_status.as_slice()),
-                            "Test #".purple(),
-                            main_index.to_string().yellow(),
-                            if status {
-                                "completed successfully".green()
-                            } else {
-                                "failed".red()
-                            },
-                            format!(
-                                "{:?}",
-                                Duration::from_millis(
-                                    (detailed_status
-                                        .iter()
-                                        .map(|elm| elm.time_elapsed.as_millis())
-                                        .sum::<u128>()
-                                        / detailed_status.len() as u128)
-                                        as u64
-                                )
-                            )
-                            .green()
-                            .italic()
-                        );
+                    };
+
+                let RunResult::RefTest {
+                    status,
+                    total_test,
+                    passed_test,
+                    detailed_status,
+                } = ref_test_result
+                else {
+                    unreachable!("Because it's a case of ouput of ref-test.");
+                };
+
+                if total_test == 0 {
+                    // A position past the last sub-test is a mistake, a label
+                    // simply matching nothing here is not.
+                    if let Some(range) = selection
+                        .ranges()
+                        .find(|range| range.is_within(main_index, file_cache.tests.len()))
+                    {
+                        return Err(RunError::Usage(format!(
+                            "No sub-test of test {main_index} matches \"{range}\"."
+                        )));
                     }
+                    continue;
                 }
-            },
+                selected_any = true;
+                all_passed &= status;
+                record_sub_test_results(&mut results, main_index, &detailed_status);
+
+                print_ref_testcases_detailed(
+                    _test_iterator(input, expected_output.as_ref())?,
+                    detailed_status.as_slice(),
+                    show_full,
+                )?;
+
+                // printing fancy test.
+                println!(
+                    "\r* {} [{}]{} {}{} {} in average of {}",
+                    if status { "✅" } else { "❌" },
+                    (passed_test as f32 / total_test as f32 * 100.0)
+                        .to_string()
+                        .yellow(),
+                    _ref_testcases_minimized(detailed_status.as_slice()),
+                    "Test #".purple(),
+                    main_index.to_string().yellow(),
+                    if status {
+                        "completed successfully".green()
+                    } else {
+                        "failed".red()
+                    },
+                    format!(
+                        "{:?}",
+                        Duration::from_millis(
+                            (detailed_status
+                                .iter()
+                                .map(|elm| elm.time_elapsed.as_millis())
+                                .sum::<u128>()
+                                / detailed_status.len() as u128) as u64
+                        )
+                    )
+                    .green()
+                    .italic()
+                );
+            }
         };
     }
 
+    if !selected_any {
+        return Err(RunError::Usage(format!(
+            "No test matches \"{expression}\"."
+        )));
+    }
+
+    save_results(src_path, profile, results)?;
+
+    Ok(TestOutcome::from_all_passed(all_passed))
+}
+
+/// Prints the result of a test stored in the cache, along with its input,
+/// output and expected output when it failed. Tests without an expected
+/// output always show their output.
+fn print_string_test_result(
+    index: usize,
+    verdict: Verdict,
+    time_elapsed: Duration,
+    input: &str,
+    expected_output: Option<&str>,
+    output: &[u8],
+) -> io::Result<()> {
+    let cols = terminal::size()?.0 as usize;
+    let rows = 15;
+    let output = String::from_utf8_lossy(output);
+    let output_block = |output: &str| padded_string(output, cols, rows, !output.contains('\n'));
+
+    if verdict.is_passed() {
+        println!(
+            "* ✅ {}{} {} in {}.",
+            "Test #".purple(),
+            index.to_string().yellow(),
+            "completed successfully".green(),
+            format!("{:?}", time_elapsed).green().italic()
+        );
+
+        if expected_output.is_none() {
+            println!("{}\n{}", "Output:".bold(), output_block(&output));
+        }
+        return Ok(());
+    }
+
+    println!(
+        "* ❌ {}{} {}. Taking: {}\n{}\n{}\n{}\n{}",
+        "Test #".purple(),
+        index.to_string().yellow(),
+        verdict.to_string().red(),
+        format!("{:?}", time_elapsed).green().italic(),
+        "Input:".bold(),
+        padded_string(input, cols, rows, input.lines().count() == 1).blue(),
+        "Output:".bold(),
+        output_block(&output).red(),
+    );
+
+    if let Some(expected_output) = expected_output {
+        println!(
+            "{}\n{}",
+            "Expected-output:".bold(),
+            padded_string(
+                expected_output,
+                cols,
+                rows,
+                expected_output.lines().count() == 1
+            )
+            .green()
+        );
+    }
+
+    Ok(())
+}
+
+fn record_sub_test_results(
+    results: &mut BTreeMap<String, TestRecord>,
+    main_index: usize,
+    detailed_status: &[DetailedStatus],
+) {
+    for status in detailed_status {
+        results.insert(
+            format!("{}.{}", main_index, status.ref_test_index + 1),
+            TestRecord {
+                verdict: status.verdict,
+                time_elapsed: status.time_elapsed,
+            },
+        );
+    }
+}
+
+/// Keeps the verdicts of `results` as the last ones of `src_path`, for
+/// `test run --failed`, and adds the run to its history.
+fn save_results(
+    src_path: &Path,
+    profile: Option<&str>,
+    results: BTreeMap<String, TestRecord>,
+) -> Result<(), RunError> {
+    if results.is_empty() {
+        return Ok(());
+    }
+
+    let filename = cache_key(src_path)?;
+
+    // Reloaded, as binaries recompiled while testing are recorded meanwhile.
+    if let Some(mut file_cache) = get_file(filename)? {
+        file_cache.last_verdicts.extend(
+            results
+                .iter()
+                .map(|(selector, record)| (selector.clone(), record.verdict)),
+        );
+        put_file(filename, file_cache)?;
+    }
+
+    let source_hash =
+        HEXUPPER.encode(sha256_digest(io::BufReader::new(File::open(src_path)?))?.as_ref());
+    append_run(filename, HistoryRun::new(source_hash, profile, results))?;
+
     Ok(())
 }
 
@@ -339,7 +358,18 @@ pub fn print_ref_testcases_detailed(
                 continue;
             };
 
-            if detailed_status.status {
+            // Test data is raw bytes, only rendered lossily for display.
+            let output = String::from_utf8_lossy(&detailed_status.output);
+            let shown_bytes = match ignore_terminal_size {
+                true => usize::MAX,
+                false => SHOWN_BYTES,
+            };
+            let output_block = |output: &str| match ignore_terminal_size {
+                true => output.to_string(),
+                false => padded_string(output, cols, rows, output.lines().count() == 1),
+            };
+
+            if detailed_status.verdict.is_passed() {
                 println!(
                     "[{}] {}{}. Taking: {}",
                     "P".green(),
@@ -349,12 +379,20 @@ pub fn print_ref_testcases_detailed(
                         .green()
                         .italic(),
                 );
+
+                if expected_output.is_none() {
+                    println!("{}\n{}", "Output:".bold(), output_block(&output));
+                }
             } else {
+                // Inputs left in their file are only read to show failures.
+                let input = input.head(shown_bytes)?;
+                let input = String::from_utf8_lossy(&input);
                 println!(
-                    "[{}] {}{}. Taking: {}\n{}\n{}\n{}\n{}\n{}\n{}",
+                    "[{}] {}{} {}. Taking: {}\n{}\n{}\n{}\n{}",
                     "-".red(),
                     "SubTest #".purple().bold(),
-                    index.to_string().yellow(),
+                    (index + 1).to_string().yellow(),
+                    detailed_status.verdict.to_string().red(),
                     format!("{:?}", detailed_status.time_elapsed)
                         .green()
                         .italic(),
@@ -365,30 +403,18 @@ pub fn print_ref_testcases_detailed(
                         padded_string(&input, cols, rows, input.lines().count() == 1).blue()
                     },
                     "Output:".bold(),
-                    if ignore_terminal_size {
-                        detailed_status.output.red()
-                    } else {
-                        padded_string(
-                            &detailed_status.output,
-                            cols,
-                            rows,
-                            detailed_status.output.lines().count() == 1,
-                        )
-                        .red()
-                    },
-                    "Expected-output:".bold(),
-                    if ignore_terminal_size {
-                        expected_output.green()
-                    } else {
-                        padded_string(
-                            &expected_output,
-                            cols,
-                            rows,
-                            expected_output.lines().count() == 1,
-                        )
-                        .green()
-                    },
+                    output_block(&output).red(),
                 );
+
+                if let Some(expected_output) = expected_output {
+                    let expected_output = expected_output.head(shown_bytes)?;
+                    let expected_output = String::from_utf8_lossy(&expected_output);
+                    println!(
+                        "{}\n{}",
+                        "Expected-output:".bold(),
+                        output_block(&expected_output).green()
+                    );
+                }
             }
 
             println!("{}", "-".repeat(cols));
@@ -411,7 +437,7 @@ pub fn _ref_testcases_minimized(detailed_statuses: &[DetailedStatus]) -> String
 
     result.push('[');
     for detailed_status in detailed_statuses {
-        match detailed_status.status {
+        match detailed_status.verdict.is_passed() {
             true => result.push_str(&format!("{}", "P".green())),
             false => result.push_str(&format!("{}", "-".red())),
         };
@@ -422,137 +448,267 @@ pub fn _ref_testcases_minimized(detailed_statuses: &[DetailedStatus]) -> String
     result
 }
 
-pub fn run(src_path: &Path, force_recompile: bool, show_full: bool) -> Result<(), RunError> {
-    assert!(src_path.exists());
+/// Loads the cache of `path`, applies `update` to it and saves it back.
+fn update_file_cache(path: &Path, update: impl FnOnce(&mut FileCache)) -> Result<(), RunError> {
+    let filename = cache_key(path)?;
 
-    let filename = src_path.file_name().unwrap().to_str().unwrap();
+    let Some(mut file_cache) = get_file(filename)? else {
+        return Err(RunError::Usage(format!(
+            "{path:?} is not tracked yet, add a test to it first."
+        )));
+    };
 
-    let target_file = File::open(src_path)?;
-    let target_reader = BufReader::new(target_file);
-    let target_hashed = HEXUPPER.encode(sha256_digest(target_reader)?.as_ref());
+    update(&mut file_cache);
+    put_file(filename, file_cache)?;
 
-    let config = get_config()?;
+    Ok(())
+}
 
-    if !config.binary_dir_path.is_dir() {
-        return Err(RunError::Other("Binary path not found.".to_string()));
+/// Switches `path` to reading `io_files.input` and writing `io_files.output`,
+/// or back to stdin and stdout.
+pub fn set_io_files(path: &Path, io_files: Option<IoFiles>) -> Result<(), RunError> {
+    if let Some(io_files) = &io_files {
+        for name in [&io_files.input, &io_files.output] {
+            if Path::new(name).file_name() != Some(name.as_ref()) {
+                return Err(RunError::Usage(format!(
+                    "\"{name}\" must be a plain file name, it is created in the test's own directory."
+                )));
+            }
+        }
     }
 
-    let file_cache = match get_file(filename) {
-        Ok(Some(file_cache)) if file_cache.source_hash == target_hashed && !force_recompile => {
-            log!(info, "Cache hit for {src_path:?}. Skipping recompilation.");
-            file_cache
+    update_file_cache(path, |file_cache| file_cache.io_files = io_files.clone())?;
+
+    match &io_files {
+        Some(IoFiles { input, output }) => {
+            log!(
+                success,
+                "Tests of {path:?} now read \"{input}\" and write \"{output}\"."
+            );
         }
-        Ok(Some(file_cache)) => {
-            log!(warn, "Re-compiling binary...");
+        None => {
+            log!(success, "Tests of {path:?} now use stdin and stdout.");
+        }
+    }
 
-            recompile_binary(src_path).map_err(RunError::CompilationError)?;
+    Ok(())
+}
 
-            let file_cache = FileCache {
-                source_hash: target_hashed,
-                tests: file_cache.tests,
-            };
+/// Output limit given on the command line, else the one set for the file.
+pub fn effective_output_limit(file_cache: &FileCache, output_limit: Option<usize>) -> usize {
+    output_limit
+        .or(file_cache.output_limit)
+        .unwrap_or(DEFAULT_OUTPUT_LIMIT)
+}
 
-            put_file(filename, file_cache.clone())?;
+/// Sets how many bytes the tests of `path` may print, or restores the default.
+pub fn set_output_limit(path: &Path, output_limit: Option<usize>) -> Result<(), RunError> {
+    update_file_cache(path, |file_cache| file_cache.output_limit = output_limit)?;
 
-            file_cache
+    match output_limit {
+        Some(output_limit) => {
+            log!(
+                success,
+                "Tests of {path:?} may now print up to {output_limit} bytes."
+            );
         }
-        _ => {
-            log!(warn, "Re-compiling binary...");
+        None => {
+            log!(
+                success,
+                "Tests of {path:?} use the default output limit of {DEFAULT_OUTPUT_LIMIT} bytes."
+            );
+        }
+    }
 
-            recompile_binary(src_path).map_err(RunError::CompilationError)?;
+    Ok(())
+}
 
-            let file_cache = FileCache {
-                source_hash: target_hashed,
-                tests: Vec::new(),
-            };
+/// Sets how long the tests of `path` may run, or removes the limit.
+pub fn set_time_limit(path: &Path, time_limit: Option<Duration>) -> Result<(), RunError> {
+    update_file_cache(path, |file_cache| file_cache.time_limit = time_limit)?;
 
-            put_file(filename, file_cache.clone())?;
+    match time_limit {
+        Some(time_limit) => {
+            log!(
+                success,
+                "Tests of {path:?} are now stopped after {time_limit:?}."
+            );
+        }
+        None => {
+            log!(success, "Tests of {path:?} have no time limit anymore.");
+        }
+    }
+
+    Ok(())
+}
+
+/// Sets how much memory the tests of `path` may use, or removes the limit.
+pub fn set_memory_limit(path: &Path, memory_limit: Option<usize>) -> Result<(), RunError> {
+    update_file_cache(path, |file_cache| file_cache.memory_limit = memory_limit)?;
 
-            file_cache
+    match memory_limit {
+        Some(memory_limit) => {
+            log!(
+                success,
+                "Tests of {path:?} may now use up to {memory_limit} bytes of memory."
+            );
         }
-    };
+        None => {
+            log!(success, "Tests of {path:?} have no memory limit anymore.");
+        }
+    }
 
-    let mut score: usize = 0;
+    Ok(())
+}
+
+pub fn run(
+    src_path: &Path,
+    profile: Option<&str>,
+    force_recompile: bool,
+    show_full: bool,
+    output_limit: Option<usize>,
+    failed: bool,
+    first_failure: bool,
+) -> Result<TestOutcome, RunError> {
+    if failed {
+        let file_cache = get_file(cache_key(src_path)?)?;
+        let failed_tests = file_cache
+            .as_ref()
+            .map(FileCache::failed_tests)
+            .unwrap_or_default();
+
+        if failed_tests.is_empty() {
+            log!(info, "No failed test recorded.");
+            return Ok(TestOutcome::Passed);
+        }
+
+        return run_at(
+            src_path,
+            &failed_tests.join(","),
+            profile,
+            force_recompile,
+            show_full,
+            output_limit,
+            first_failure,
+        );
+    }
+
+    let config = get_config()?;
+
+    if !config.binary_dir_path.is_dir() {
+        return Err(RunError::Config("Binary path not found.".to_string()));
+    }
+
+    let file_cache = prepare_binary(&config, src_path, profile, force_recompile)?;
+
+    let target = ExecutionTarget {
+        config: &config,
+        src_path,
+        profile,
+        output_limit: effective_output_limit(&file_cache, output_limit),
+        time_limit: file_cache.time_limit,
+        memory_limit: file_cache.memory_limit,
+        io_files: file_cache.io_files.as_ref(),
+    };
 
     if file_cache.tests.is_empty() {
         log!(info, "No test found.");
-        return Ok(());
+        return Ok(TestOutcome::Passed);
     }
 
-    for (index, test) in file_cache.tests.iter().enumerate() {
-        score += match run_core(test, src_path, filename, &config.binary_dir_path) {
+    let mut results = BTreeMap::new();
+    let score = run_tests(
+        &file_cache.tests,
+        &target,
+        show_full,
+        first_failure,
+        &mut results,
+    )?;
+    save_results(src_path, profile, results)?;
+
+    Ok(TestOutcome::from_all_passed(
+        score == file_cache.tests.len(),
+    ))
+}
+
+/// Runs every test against `target`, printing each result followed by a
+/// summary, and returns how many of them passed. The result of every test
+/// and sub-test is added to `results`, keyed by its selector.
+pub fn run_tests(
+    tests: &[Test],
+    target: &ExecutionTarget,
+    show_full: bool,
+    first_failure: bool,
+    results: &mut BTreeMap<String, TestRecord>,
+) -> Result<usize, RunError> {
+    let mut score: usize = 0;
+
+    for (index, test) in tests.iter().enumerate() {
+        if first_failure && score < index {
+            log!(info, "Stopped at the first failure.");
+            break;
+        }
+
+        score += match run_core(test, target, None, first_failure) {
             Ok(RunResult::SingleTest {
-                status,
+                verdict,
                 time_elapsed,
                 output,
             }) => {
+                let status = verdict.is_passed();
+                results.insert(
+                    (index + 1).to_string(),
+                    TestRecord {
+                        verdict,
+                        time_elapsed,
+                    },
+                );
+
                 let (input, expected_output) = match test {
                     Test::StringTest {
                         input,
                         expected_output,
+                        ..
                     } => (input, expected_output),
                     _ => unreachable!("Because it's a case of output single-test."),
                 };
 
-                if status {
-                    println!(
-                        "* ✅ {}{} {} in {}.",
-                        "Test #".purple(),
-                        index.to_string().yellow(),
-                        "completed successfully".green(),
-                        format!("{:?}", time_elapsed).green().italic()
-                    );
-                } else {
-                    // println!(
-                    //     "* ❌ {}{} {} in {}.",
-                    //     "Test #".purple(),
-                    //     index.to_string().yellow(),
-                    //     "failed after".red(),
-                    //     format!("{:?}", time_elapsed).green().italic()
-                    // );
-
-                    let cols = terminal::size()?.0 as usize;
-                    let rows = 15;
-
-                    println!(
-                        "* ❌ {}{}. Taking: {}\n{}\n{}\n{}\n{}\n{}\n{}",
-                        "Test #".purple(),
-                        index.to_string().yellow(),
-                        format!("{:?}", time_elapsed).green().italic(),
-                        "Input:".bold(),
-                        padded_string(input, cols, rows, input.lines().count() == 1).blue(),
-                        "Output:".bold(),
-                        padded_string(&output, cols, rows, output.lines().count() == 1).red(),
-                        "Expected-output:".bold(),
-                        padded_string(
-                            expected_output,
-                            cols,
-                            rows,
-                            expected_output.lines().count() == 1
-                        )
-                        .green(),
-                    );
-                }
+                print_string_test_result(
+                    index + 1,
+                    verdict,
+                    time_elapsed,
+                    input,
+                    expected_output.as_deref(),
+                    &output,
+                )?;
 
                 status as usize
             }
 
+            Ok(RunResult::RefTest { total_test: 0, .. }) => {
+                log!(warn, "Test #{} links a file without any test.", index + 1);
+                0
+            }
+
             Ok(RunResult::RefTest {
                 status,
                 total_test,
                 passed_test,
                 detailed_status,
             }) => {
+                record_sub_test_results(results, index + 1, &detailed_status);
+
                 let (input, expected_output) = match test {
                     Test::RefTest {
                         input,
                         expected_output,
+                        ..
                     } => (input, expected_output),
                     _ => unreachable!("Because it's a case of ouput of ref-test."),
                 };
 
                 print_ref_testcases_detailed(
-                    _test_iterator(input, expected_output.as_ref()).map_err(RunError::Other)?,
+                    _test_iterator(input, expected_output.as_ref())?,
                     detailed_status.as_slice(),
                     show_full,
                 )?;
@@ -566,7 +722,7 @@ pub fn run(src_path: &Path, force_recompile: bool, show_full: bool) -> Result<()
                         .yellow(),
                     _ref_testcases_minimized(detailed_status.as_slice()),
                     "Test #".purple(),
-                    index.to_string().yellow().italic(),
+                    (index + 1).to_string().yellow().italic(),
                     if status {
                         "completed successfully".green()
                     } else {
@@ -588,14 +744,16 @@ pub fn run(src_path: &Path, force_recompile: bool, show_full: bool) -> Result<()
                 status as usize
             }
 
+            // The binary can't be rebuilt, so every remaining test would fail alike.
+            Err(error @ RunError::CompilationError(_)) => return Err(error),
             Err(error) => {
-                println!("{}", error);
+                log!(error, "{error}");
                 0
             }
         };
     }
 
-    if score == file_cache.tests.len() {
+    if score == tests.len() {
         println!(
             "{}",
             format!("* ✅ Test completed, {score} tests passed out of a total of {score}.").green()
@@ -605,24 +763,230 @@ pub fn run(src_path: &Path, force_recompile: bool, show_full: bool) -> Result<()
             "{}",
             format!(
                 "* ❌ Test failed, {score} tests passed out of a total of {}.",
-                file_cache.tests.len()
+                tests.len()
             )
             .red()
         );
     }
 
-    Ok(())
+    Ok(score)
+}
+
+/// Result of one tracked file in `run_all`.
+enum FileVerdict {
+    Tested { passed: usize, total: usize },
+    NoTests,
+    CompilationError,
+    Error,
+    Missing,
+}
+
+impl FileVerdict {
+    fn is_failure(&self) -> bool {
+        match self {
+            FileVerdict::Tested { passed, total } => passed != total,
+            FileVerdict::CompilationError | FileVerdict::Error => true,
+            FileVerdict::NoTests | FileVerdict::Missing => false,
+        }
+    }
+}
+
+/// Whether the binary of `src_path` would be rebuilt before testing.
+fn is_changed(config: &Files, src_path: &Path, filename: &str, profile: Option<&str>) -> bool {
+    let cached_build_key = config
+        .files
+        .get(filename)
+        .and_then(|file_cache| file_cache.build_key(profile));
+
+    match (
+        cached_build_key,
+        current_build_key(config, src_path, profile),
+    ) {
+        (Some(cached_build_key), Ok(build_key)) => {
+            !cached_build_key.stale_reasons(&build_key).is_empty()
+        }
+        _ => true,
+    }
 }
 
-pub fn add(path: &Path, input: &str, expected_output: &str) -> Result<(), io::Error> {
-    assert!(path.exists());
+fn test_tracked_file(
+    config: &Files,
+    src_path: &Path,
+    profile: Option<&str>,
+    output_limit: Option<usize>,
+) -> Result<FileVerdict, RunError> {
+    let file_cache = prepare_binary(config, src_path, profile, false)?;
+
+    if file_cache.tests.is_empty() {
+        return Ok(FileVerdict::NoTests);
+    }
+
+    let target = ExecutionTarget {
+        config,
+        src_path,
+        profile,
+        output_limit: effective_output_limit(&file_cache, output_limit),
+        time_limit: file_cache.time_limit,
+        memory_limit: file_cache.memory_limit,
+        io_files: file_cache.io_files.as_ref(),
+    };
+    let mut results = BTreeMap::new();
+    let passed = run_tests(&file_cache.tests, &target, false, false, &mut results)?;
+    save_results(src_path, profile, results)?;
+
+    Ok(FileVerdict::Tested {
+        passed,
+        total: file_cache.tests.len(),
+    })
+}
+
+/// Runs the tests of every tracked file, recompiling stale binaries first,
+/// and prints a per-file summary table in the layout of `status`.
+pub fn run_all(
+    changed_only: bool,
+    profile: Option<&str>,
+    output_limit: Option<usize>,
+) -> Result<TestOutcome, RunError> {
+    let config = get_config()?;
+
+    if !config.binary_dir_path.is_dir() {
+        return Err(RunError::Config("Binary path not found.".to_string()));
+    }
+
+    let mut filenames: Vec<&String> = config.files.keys().collect();
+    filenames.sort();
+
+    let mut verdicts: Vec<(&String, FileVerdict)> = Vec::new();
+    let mut skipped = 0;
+
+    for filename in filenames {
+        let src_path = Path::new(filename);
+        if !src_path.exists() {
+            verdicts.push((filename, FileVerdict::Missing));
+            continue;
+        }
+        let src_path = fs::canonicalize(src_path)?;
+
+        if changed_only && !is_changed(&config, &src_path, filename, profile) {
+            skipped += 1;
+            continue;
+        }
+
+        println!("{}", format!("Testing {filename}").blue().bold());
+
+        let verdict = match test_tracked_file(&config, &src_path, profile, output_limit) {
+            Ok(verdict) => verdict,
+            Err(error @ RunError::CompilationError(_)) => {
+                log!(error, "{error}");
+                FileVerdict::CompilationError
+            }
+            Err(error) => {
+                log!(error, "{error}");
+                FileVerdict::Error
+            }
+        };
+        verdicts.push((filename, verdict));
+    }
+
+    print_run_all_summary(&verdicts, skipped);
+
+    let compilation_errors = verdicts
+        .iter()
+        .filter(|(_, verdict)| matches!(verdict, FileVerdict::CompilationError))
+        .count();
+    if compilation_errors > 0 {
+        return Err(RunError::CompilationError(format!(
+            "{compilation_errors} of {} files failed to compile.",
+            verdicts.len()
+        )));
+    }
+
+    Ok(TestOutcome::from_all_passed(
+        !verdicts.iter().any(|(_, verdict)| verdict.is_failure()),
+    ))
+}
+
+fn print_run_all_summary(verdicts: &[(&String, FileVerdict)], skipped: usize) {
+    println!(
+        "{:<4} {:<20} {:<10} {:<10} {:<10}",
+        "No.".cyan(),
+        "Filename".cyan(),
+        "Passed".cyan(),
+        "Tests".cyan(),
+        "Result".cyan()
+    );
+
+    let (mut passed_tests, mut total_tests) = (0, 0);
+
+    for (index, (filename, verdict)) in verdicts.iter().enumerate() {
+        let filename = limited_string(filename, 20, 1, false);
+        let (passed, total, result) = match verdict {
+            FileVerdict::Tested { passed, total } => {
+                passed_tests += passed;
+                total_tests += total;
+
+                (
+                    passed.to_string(),
+                    total.to_string(),
+                    if passed == total {
+                        "passed".green()
+                    } else {
+                        "failed".red()
+                    },
+                )
+            }
+            FileVerdict::NoTests => ("-".to_string(), "0".to_string(), "no tests".yellow()),
+            FileVerdict::CompilationError => {
+                ("-".to_string(), "-".to_string(), "compile error".red())
+            }
+            FileVerdict::Error => ("-".to_string(), "-".to_string(), "error".red()),
+            FileVerdict::Missing => ("-".to_string(), "-".to_string(), "missing".red()),
+        };
+
+        println!(
+            "{:<4} {:<20} {:<10} {:<10} {}",
+            (index + 1).to_string().cyan(),
+            match verdict {
+                FileVerdict::Missing => filename.red().strikethrough(),
+                verdict if verdict.is_failure() => filename.red(),
+                _ => filename.green(),
+            },
+            passed.green(),
+            total.magenta(),
+            result
+        );
+    }
+
+    if skipped > 0 {
+        println!("{}", format!("Skipped {skipped} unchanged files.").italic());
+    }
 
-    let filename = path.file_name().unwrap().to_str().unwrap();
+    let summary = format!(
+        "{passed_tests} tests passed out of a total of {total_tests} in {} files.",
+        verdicts.len()
+    );
+    if verdicts.iter().any(|(_, verdict)| verdict.is_failure()) {
+        println!("{}", format!("* ❌ {summary}").red());
+    } else {
+        println!("{}", format!("* ✅ {summary}").green());
+    }
+}
+
+pub fn add(
+    path: &Path,
+    input: &str,
+    expected_output: Option<&str>,
+    run_args: RunArgs,
+    labels: TestLabels,
+) -> Result<(), RunError> {
+    let filename = cache_key(path)?;
 
     if let Ok(Some(mut file_cache)) = get_file(filename) {
         file_cache.tests.push(Test::StringTest {
             input: input.to_string(),
-            expected_output: expected_output.to_string(),
+            expected_output: expected_output.map(str::to_string),
+            run_args,
+            labels,
         });
 
         put_file(filename, file_cache)?;
@@ -639,13 +1003,15 @@ pub fn add(path: &Path, input: &str, expected_output: &str) -> Result<(), io::Er
 
     put_file(
         filename,
-        FileCache {
-            source_hash: hashed,
-            tests: vec![Test::StringTest {
+        FileCache::new(
+            hashed,
+            vec![Test::StringTest {
                 input: input.to_string(),
-                expected_output: expected_output.to_string(),
+                expected_output: expected_output.map(str::to_string),
+                run_args,
+                labels,
             }],
-        },
+        ),
     )?;
 
     log!(success, "Successfuly add test.");
@@ -653,11 +1019,35 @@ pub fn add(path: &Path, input: &str, expected_output: &str) -> Result<(), io::Er
     Ok(())
 }
 
+/// Aggregated result of a test command, mapped to the process exit code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TestOutcome {
+    Passed,
+    Failed,
+}
+
+impl TestOutcome {
+    pub fn from_all_passed(all_passed: bool) -> Self {
+        if all_passed {
+            TestOutcome::Passed
+        } else {
+            TestOutcome::Failed
+        }
+    }
+
+    pub fn exit_code(self) -> i32 {
+        match self {
+            TestOutcome::Passed => 0,
+            TestOutcome::Failed => 1,
+        }
+    }
+}
+
 pub enum RunResult {
     SingleTest {
-        status: bool,
+        verdict: Verdict,
         time_elapsed: Duration,
-        output: String,
+        output: Vec<u8>,
     },
     RefTest {
         status: bool,
@@ -669,37 +1059,49 @@ pub enum RunResult {
 
 pub struct DetailedStatus {
     pub ref_test_index: usize,
-    pub status: bool,
+    pub verdict: Verdict,
     pub time_elapsed: Duration,
-    pub output: String,
+    pub output: Vec<u8>,
 }
 
 type TestIterator = Box<dyn Iterator<Item = Result<SimpleTest, Box<dyn Error>>>>;
 
-fn _test_iterator(
+pub fn _test_iterator(
     input_path: &Path,
     expected_output_path: Option<&PathBuf>,
-) -> Result<TestIterator, String> {
-    if !input_path.exists()
-        || (expected_output_path.is_some() && !expected_output_path.as_ref().unwrap().exists())
-    {
-        return Err(format!(
-            "The input file {} {} the expected output file {}. Please ensure both files exist and the paths are correct.",
-            if input_path.exists() { "exists" } else { "does not exist" },
-            if !input_path.exists() && expected_output_path.is_some() && !expected_output_path.unwrap().exists() { "and" } else { "but" },
-            if expected_output_path.unwrap().exists() { "exists" } else { "does not exist" }
-        ));
+) -> Result<TestIterator, RunError> {
+    let input_path = resolve_project_path(input_path)?;
+    let expected_output_path = expected_output_path
+        .map(|path| resolve_project_path(path))
+        .transpose()?;
+
+    let input_path = input_path.as_path();
+    let expected_output_path = expected_output_path.as_deref();
+
+    let input_exists = input_path.exists();
+    let expected_output_exists = expected_output_path.is_none_or(Path::exists);
+
+    if !input_exists || !expected_output_exists {
+        return Err(RunError::Usage(match expected_output_path {
+            Some(_) => format!(
+                "The input file {} {} the expected output file {}. Please ensure both files exist and the paths are correct.",
+                if input_exists { "exists" } else { "does not exist" },
+                if !input_exists && !expected_output_exists { "and" } else { "but" },
+                if expected_output_exists { "exists" } else { "does not exist" }
+            ),
+            None => format!("The test file {input_path:?} does not exist."),
+        }));
     }
 
     // Attempt to read the input test file
-    let input_tests =
-        read_test_file(input_path).map_err(|_| "Failed to read the input file.".to_string())?;
+    let input_tests = read_test_file(input_path)
+        .map_err(|err| RunError::Parse(format!("Failed to read {input_path:?}: {err}")))?;
 
     // Attempt to read the expected output file, if provided
     let expected_output_tests = match expected_output_path {
         Some(path) => Some(
             read_test_file(path)
-                .map_err(|_| "Failed to read the expected output file.".to_string())?,
+                .map_err(|err| RunError::Parse(format!("Failed to read {path:?}: {err}")))?,
         ),
         None => None,
     };
@@ -707,93 +1109,262 @@ fn _test_iterator(
     // Create the test iterator
     let test_iterator: Box<dyn Iterator<Item = Result<SimpleTest, Box<dyn Error>>>> =
         match expected_output_tests {
-            Some(output_tests) => Box::new(
-                merge_test_file(input_tests, output_tests)
-                    .map_err(|_| "Failed to merge the input and expected output files.")?,
-            ),
+            Some(output_tests) => {
+                Box::new(merge_test_file(input_tests, output_tests).map_err(|err| {
+                    RunError::Parse(format!(
+                        "Failed to merge the input and expected output files: {err}"
+                    ))
+                })?)
+            }
             None => Box::new(input_tests),
         };
 
     Ok(test_iterator)
 }
 
+/// Rewrites a test file, merged with its expected output file if given, as a
+/// section file written to `output_path`, `<name>.tests` next to it by default.
+pub fn convert(
+    input_path: &Path,
+    expected_output_path: Option<&PathBuf>,
+    output_path: Option<&Path>,
+) -> Result<(), RunError> {
+    let tests = _test_iterator(input_path, expected_output_path)?
+        .collect::<Result<Vec<SimpleTest>, _>>()
+        .map_err(|err| RunError::Parse(err.to_string()))?;
+
+    let output_path = output_path.map_or_else(
+        || input_path.with_extension(SECTION_FILE_EXTENSION),
+        Path::to_path_buf,
+    );
+    if output_path.exists() {
+        return Err(RunError::Usage(format!(
+            "{output_path:?} already exists, remove it or pick another path with --output."
+        )));
+    }
+
+    let mut writer = BufWriter::new(File::create_new(&output_path)?);
+    write_section_file(&mut writer, None, &tests)?;
+    writer.flush()?;
+
+    log!(
+        success,
+        "Converted {} test{} into {output_path:?}.",
+        tests.len(),
+        if tests.len() == 1 { "" } else { "s" }
+    );
+
+    Ok(())
+}
+
+/// Reads every test file linked by the tests of `path` through without
+/// running anything, reporting the first error of each.
+pub fn lint(path: &Path) -> Result<(), RunError> {
+    let filename = cache_key(path)?;
+
+    let Some(file_cache) = get_file(filename)? else {
+        return Err(RunError::Usage(format!(
+            "{path:?} is not tracked yet, add a test to it first."
+        )));
+    };
+
+    let mut linked = 0;
+    let mut failing = 0;
+
+    for (index, test) in file_cache.tests.iter().enumerate() {
+        let Test::RefTest {
+            input,
+            expected_output,
+            ..
+        } = test
+        else {
+            continue;
+        };
+        linked += 1;
+
+        let files = match expected_output {
+            Some(expected_output) => format!("{input:?} -> {expected_output:?}"),
+            None => format!("{input:?}"),
+        };
+        let count = _test_iterator(input, expected_output.as_ref()).and_then(|mut tests| {
+            tests.try_fold(0, |count, test| {
+                test.map(|_| count + 1)
+                    .map_err(|err| RunError::Parse(err.to_string()))
+            })
+        });
+
+        match count {
+            Ok(0) => {
+                failing += 1;
+                println!(
+                    "* ❌ {}{} {files} {}",
+                    "Test #".purple(),
+                    (index + 1).to_string().yellow(),
+                    "holds no test.".red()
+                );
+            }
+            Ok(count) => println!(
+                "* ✅ {}{} {files} {}",
+                "Test #".purple(),
+                (index + 1).to_string().yellow(),
+                format!(
+                    "reads as {count} test{}.",
+                    if count == 1 { "" } else { "s" }
+                )
+                .green()
+            ),
+            Err(err) => {
+                failing += 1;
+                println!(
+                    "* ❌ {}{} {files}\n{}",
+                    "Test #".purple(),
+                    (index + 1).to_string().yellow(),
+                    match err {
+                        RunError::Parse(message) => message,
+                        err => err.to_string(),
+                    }
+                    .red()
+                );
+            }
+        }
+    }
+
+    match (linked, failing) {
+        (0, _) => {
+            log!(info, "{path:?} doesn't link any test file.");
+        }
+        (_, 0) => {
+            log!(success, "All {linked} linked test files read fine.");
+        }
+        _ => {
+            return Err(RunError::Parse(format!(
+                "{failing} of {linked} linked test files have errors."
+            )))
+        }
+    }
+
+    Ok(())
+}
+
+/// A test or sub-test flattened out of the tests of a file.
+pub struct TestCase {
+    /// Selector of the case, e.g. `3.2`.
+    pub selector: String,
+    pub input: TestInput,
+    pub expected_output: Option<TestInput>,
+    pub run_args: RunArgs,
+}
+
+/// Reads every test and sub-test of `tests` matched by `selection`, in file order.
+/// Inputs left in their test file stay on disk until the cases run.
+pub fn selected_cases(tests: &[Test], selection: &Selection) -> Result<Vec<TestCase>, RunError> {
+    let mut cases = Vec::new();
+
+    for (index, test) in tests.iter().enumerate() {
+        let main_index = index + 1;
+
+        if !selection.may_select(main_index, test.labels()) {
+            continue;
+        }
+
+        match test {
+            Test::StringTest {
+                input,
+                expected_output,
+                run_args,
+                labels,
+            } => {
+                if selection.selects(TestPosition::test(main_index), &[labels]) {
+                    cases.push(TestCase {
+                        selector: main_index.to_string(),
+                        input: input.as_bytes().to_vec().into(),
+                        expected_output: expected_output
+                            .as_ref()
+                            .map(|output| output.as_bytes().to_vec().into()),
+                        run_args: run_args.clone(),
+                    });
+                }
+            }
+
+            Test::RefTest {
+                input,
+                expected_output,
+                run_args,
+                labels,
+            } => {
+                let sub_tests = _test_iterator(input, expected_output.as_ref())?
+                    .collect::<Result<Vec<SimpleTest>, _>>()
+                    .map_err(|err| RunError::Parse(err.to_string()))?;
+                let sub_test_count = sub_tests.len();
+
+                for (sub_index, sub_test) in (1..).zip(sub_tests) {
+                    let position =
+                        TestPosition::sub_test(main_index, sub_index, Some(sub_test_count));
+                    if !selection.selects(position, &[labels, &sub_test.labels]) {
+                        continue;
+                    }
+
+                    cases.push(TestCase {
+                        selector: format!("{main_index}.{sub_index}"),
+                        input: sub_test.input,
+                        expected_output: sub_test.expected_output,
+                        run_args: run_args.merged_with(&sub_test.run_args),
+                    });
+                }
+            }
+        }
+    }
+
+    Ok(cases)
+}
+
 fn _ref_test_run_core(
-    src_path: &Path,
+    target: &ExecutionTarget,
     test_iterator: TestIterator,
-    guarantree_filename: &str,
-    guarantree_binary_dir_path: &Path,
-    run_range: Option<&RangeInclusive<usize>>,
+    run_args: &RunArgs,
+    filter: Option<SubTestFilter>,
+    first_failure: bool,
 ) -> Result<RunResult, RunError> {
     let mut inner_score: usize = 0;
     let mut total_inner_tests: usize = 0;
     let mut tests_pass = true;
-    let mut only_run_at_ran: bool = false;
     let mut detailed_status: Vec<DetailedStatus> = Vec::new();
 
     #[allow(clippy::explicit_counter_loop)]
     for (inner_index, test) in test_iterator.enumerate() {
-        if let Some(run_range) = run_range {
-            if !run_range.contains(&(inner_index + 1)) {
-                continue;
-            }
-            only_run_at_ran = true;
+        let test = test.map_err(|err| RunError::Parse(err.to_string()))?;
+
+        if filter.is_some_and(|filter| !filter(inner_index + 1, &test)) {
+            continue;
         }
 
-        let Ok(SimpleTest {
+        let SimpleTest {
             input,
             expected_output,
-        }) = test
-        else {
-            break;
-        };
+            run_args: test_run_args,
+            ..
+        } = test;
 
         total_inner_tests += 1;
-        let (status, executed_output, time_elapsed) = loop {
-            let execution_status = execute_binary(
-                guarantree_binary_dir_path,
-                guarantree_filename,
-                ExecutionInput::CustomInput(input.clone()),
-            )?;
-
-            match execution_status {
-                ExecutionStatus::Successful {
-                    output,
-                    time_elapsed,
-                } => {
-                    let output_str =
-                        String::from_utf8(output.stdout).expect("Unable to convert stdout to utf8");
-                    let success = expected_output.trim() == output_str.trim();
-                    break (success, output_str, time_elapsed); // Exit loop with success and output
-                }
-                ExecutionStatus::NeedRecompilation => {
-                    log!(warn, "Recompiling need. pending recompilation.");
-                    recompile_binary(src_path).map_err(RunError::CompilationError)?;
-                    log!(success, "Successful compiling {src_path:?}");
-                }
-                ExecutionStatus::Failed(_) => {
-                    // Log the error and skip the current test
-                    break (false, String::new(), Duration::from_secs(0));
-                }
-            }
-        };
+        let (verdict, executed_output, time_elapsed) = judge(
+            target,
+            input,
+            &run_args.merged_with(&test_run_args),
+            expected_output.as_ref(),
+        )?;
 
-        tests_pass &= status;
-        inner_score += status as usize;
+        tests_pass &= verdict.is_passed();
+        inner_score += verdict.is_passed() as usize;
         detailed_status.push(DetailedStatus {
             ref_test_index: inner_index,
-            status,
+            verdict,
             time_elapsed,
             output: executed_output,
         });
-    }
 
-    if run_range.is_some() && !only_run_at_ran {
-        return Ok(RunResult::RefTest {
-            status: false,
-            total_test: 0,
-            passed_test: 0,
-            detailed_status,
-        });
+        if first_failure && !tests_pass {
+            break;
+        }
     }
 
     Ok(RunResult::RefTest {
@@ -804,78 +1375,145 @@ fn _ref_test_run_core(
     })
 }
 
+/// The retained part of `output`, noting how much of it was cut off.
+pub fn displayed_output(output: CapturedOutput) -> Vec<u8> {
+    let mut stdout = output.stdout;
+    let omitted = output.total_bytes - stdout.len();
+
+    if omitted > 0 {
+        stdout.extend_from_slice(format!("\n... {omitted} more bytes omitted").as_bytes());
+    }
+
+    stdout
+}
+
+/// Runs `input` against `target`, recompiling when its binary is missing, and
+/// judges the output. Only the leading part of the output is returned.
+pub fn judge(
+    target: &ExecutionTarget,
+    input: TestInput,
+    run_args: &RunArgs,
+    expected_output: Option<&TestInput>,
+) -> Result<(Verdict, Vec<u8>, Duration), RunError> {
+    loop {
+        let execution_status = execute_binary(
+            target,
+            ExecutionInput::CustomInput(input.clone()),
+            run_args,
+            expected_output,
+        )?;
+
+        match execution_status {
+            ExecutionStatus::Successful {
+                output,
+                time_elapsed,
+            } => {
+                // Tests without an expected output pass as long as the run does.
+                let verdict = match (expected_output, output.matches) {
+                    (None, _) | (_, Some(true)) => Verdict::Passed,
+                    _ => Verdict::WrongAnswer,
+                };
+                return Ok((verdict, displayed_output(output), time_elapsed));
+            }
+            ExecutionStatus::OutputLimitExceeded {
+                output,
+                time_elapsed,
+            } => {
+                return Ok((
+                    Verdict::OutputLimitExceeded,
+                    displayed_output(output),
+                    time_elapsed,
+                ))
+            }
+            ExecutionStatus::TimeLimitExceeded { time_elapsed } => {
+                return Ok((Verdict::TimeLimitExceeded, Vec::new(), time_elapsed));
+            }
+            ExecutionStatus::MemoryLimitExceeded { time_elapsed, .. } => {
+                return Ok((Verdict::MemoryLimitExceeded, Vec::new(), time_elapsed));
+            }
+            ExecutionStatus::Failed {
+                output,
+                time_elapsed,
+                ..
+            } => {
+                return Ok((
+                    Verdict::RuntimeError,
+                    displayed_output(output),
+                    time_elapsed,
+                ));
+            }
+            ExecutionStatus::NeedRecompilation => {
+                log!(warn, "Recompiling need. pending recompilation.");
+                recompile_binary(target.src_path, target.profile)?;
+                log!(success, "Successful compiling {:?}", target.src_path);
+            }
+        }
+    }
+}
+
+/// Decides which sub-tests of a test file run, given their 1-based index.
+type SubTestFilter<'a> = &'a dyn Fn(usize, &SimpleTest) -> bool;
+
 fn run_core(
     test: &Test,
-    src_path: &Path,
-    guarantree_filename: &str,
-    guarantree_binary_dir_path: &Path,
+    target: &ExecutionTarget,
+    filter: Option<SubTestFilter>,
+    first_failure: bool,
 ) -> Result<RunResult, RunError> {
     match test {
         Test::StringTest {
             input,
             expected_output,
-        } => loop {
-            match execute_binary(
-                guarantree_binary_dir_path,
-                guarantree_filename,
-                ExecutionInput::CustomInput(input.clone()),
-            )? {
-                ExecutionStatus::Successful {
-                    output,
-                    time_elapsed,
-                } => {
-                    let output = String::from_utf8_lossy(&output.stdout);
-                    if expected_output != output.trim() {
-                        return Ok(RunResult::SingleTest {
-                            status: false,
-                            time_elapsed,
-                            output: output.into_owned(),
-                        });
-                    }
-                    return Ok(RunResult::SingleTest {
-                        status: true,
-                        time_elapsed,
-                        output: output.into_owned(),
-                    });
-                }
-
-                ExecutionStatus::Failed(_) => {
-                    return Ok(RunResult::SingleTest {
-                        status: false,
-                        time_elapsed: Duration::from_secs(0),
-                        output: String::new(),
-                    });
-                }
+            run_args,
+            ..
+        } => {
+            let (verdict, output, time_elapsed) = judge(
+                target,
+                input.as_bytes().to_vec().into(),
+                run_args,
+                expected_output
+                    .as_ref()
+                    .map(|output| TestInput::from(output.as_bytes().to_vec()))
+                    .as_ref(),
+            )?;
 
-                ExecutionStatus::NeedRecompilation => {
-                    log!(warn, "Recompiling need. pending recompilation.");
-                    recompile_binary(src_path).map_err(RunError::CompilationError)?;
-                    log!(success, "Successful compiling {src_path:?}");
-                }
-            }
-        },
+            Ok(RunResult::SingleTest {
+                verdict,
+                time_elapsed,
+                output,
+            })
+        }
 
         Test::RefTest {
             input,
             expected_output,
+            run_args,
+            ..
         } => _ref_test_run_core(
-            src_path,
-            _test_iterator(input, expected_output.as_ref()).map_err(RunError::Other)?,
-            guarantree_filename,
-            guarantree_binary_dir_path,
-            None,
+            target,
+            _test_iterator(input, expected_output.as_ref())?,
+            run_args,
+            filter,
+            first_failure,
         ),
     }
 }
 
-pub fn add_file_link(path: &Path, file_tests: &Path) -> io::Result<()> {
-    assert!(path.exists() && file_tests.exists());
-    let filename = path.file_name().unwrap().to_str().unwrap();
+pub fn add_file_link(
+    path: &Path,
+    file_tests: &Path,
+    run_args: RunArgs,
+    labels: TestLabels,
+) -> Result<(), RunError> {
+    let filename = cache_key(path)?;
+    let file_tests = to_project_path(file_tests)?;
 
     if let Ok(Some(mut file_cache)) = get_file(filename) {
         file_cache.tests.push(Test::RefTest {
-            input: file_tests.to_path_buf(),
+            input: file_tests.clone(),
             expected_output: None,
+            run_args,
+            labels,
         });
 
         put_file(filename, file_cache)?;
@@ -892,13 +1530,15 @@ pub fn add_file_link(path: &Path, file_tests: &Path) -> io::Result<()> {
 
     put_file(
         filename,
-        FileCache {
-            source_hash: hashed,
-            tests: vec![Test::RefTest {
-                input: file_tests.to_path_buf(),
+        FileCache::new(
+            hashed,
+            vec![Test::RefTest {
+                input: file_tests.clone(),
                 expected_output: None,
+                run_args,
+                labels,
             }],
-        },
+        ),
     )?;
 
     log!(success, "Successfuly add test.");
@@ -910,14 +1550,19 @@ pub fn add_standalone_file_link(
     path: &Path,
     file_input: &Path,
     file_expected_output: &Path,
-) -> io::Result<()> {
-    assert!(path.exists() && file_input.exists() && file_expected_output.exists());
-    let filename = path.file_name().unwrap().to_str().unwrap();
+    run_args: RunArgs,
+    labels: TestLabels,
+) -> Result<(), RunError> {
+    let filename = cache_key(path)?;
+    let file_input = to_project_path(file_input)?;
+    let file_expected_output = to_project_path(file_expected_output)?;
 
     if let Ok(Some(mut file_cache)) = get_file(filename) {
         file_cache.tests.push(Test::RefTest {
-            input: file_input.to_path_buf(),
-            expected_output: Some(file_expected_output.to_path_buf()),
+            input: file_input.clone(),
+            expected_output: Some(file_expected_output.clone()),
+            run_args,
+            labels,
         });
 
         put_file(filename, file_cache)?;
@@ -934,13 +1579,15 @@ pub fn add_standalone_file_link(
 
     put_file(
         filename,
-        FileCache {
-            source_hash: hashed,
-            tests: vec![Test::RefTest {
-                input: file_input.to_path_buf(),
-                expected_output: Some(file_expected_output.to_path_buf()),
+        FileCache::new(
+            hashed,
+            vec![Test::RefTest {
+                input: file_input.clone(),
+                expected_output: Some(file_expected_output.clone()),
+                run_args,
+                labels,
             }],
-        },
+        ),
     )?;
 
     log!(success, "Successfuly add test.");
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::cache_file::Verdict;
+
+pub const DEFAULT_HISTORY_FILE: &str = "erunner_history.json";
+
+/// Runs kept per file, the oldest ones are dropped first.
+const HISTORY_LIMIT: usize = 200;
+
+/// Outcome of one test or sub-test in a run.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub struct TestRecord {
+    pub verdict: Verdict,
+    pub time_elapsed: Duration,
+}
+
+/// One run of the tests of a file, along with the source it was built from.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HistoryRun {
+    pub source_hash: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub timestamp: u64,
+    /// Keyed by selector, e.g. `3.2`.
+    pub results: BTreeMap<String, TestRecord>,
+}
+
+impl HistoryRun {
+    pub fn new(
+        source_hash: String,
+        profile: Option<&str>,
+        results: BTreeMap<String, TestRecord>,
+    ) -> Self {
+        Self {
+            source_hash,
+            profile: profile.map(str::to_string),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+            results,
+        }
+    }
+}
+
+/// Test runs of every file, kept apart from the cache as it only grows.
+#[derive(Serialize, Deserialize, Default)]
+pub struct History {
+    pub files: HashMap<String, Vec<HistoryRun>>,
+}
+
+pub fn get_history() -> io::Result<History> {
+    let history_file_path = Path::new(".").join(DEFAULT_HISTORY_FILE);
+
+    if !history_file_path.is_file() {
+        return Ok(History::default());
+    }
+
+    let file = fs::File::open(&history_file_path)?;
+    let reader = io::BufReader::new(file);
+
+    Ok(serde_json::from_reader(reader)?)
+}
+
+pub fn append_run(filename: &str, run: HistoryRun) -> io::Result<()> {
+    let history_file_path = Path::new(".").join(DEFAULT_HISTORY_FILE);
+    let mut history = get_history()?;
+
+    let runs = history.files.entry(filename.to_string()).or_default();
+    runs.push(run);
+    if runs.len() > HISTORY_LIMIT {
+        runs.drain(..runs.len() - HISTORY_LIMIT);
+    }
+
+    let file = fs::File::create(&history_file_path)?;
+    let mut writer = io::BufWriter::new(file);
+    serde_json::to_writer(&mut writer, &history)?;
+
+    Ok(())
+}
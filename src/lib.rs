@@ -1,6 +1,13 @@
 #[macro_use]
 pub mod utils;
+pub mod bundler;
 pub mod cache_file;
+pub mod dependency_scanner;
+pub mod diagnostics;
 pub mod execute;
+pub mod history_file;
+pub mod limits;
+pub mod sandbox;
+pub mod section_file;
 pub mod selector_evaluator;
 pub mod test_file;
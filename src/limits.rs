@@ -0,0 +1,137 @@
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How a process ended and what it used.
+pub struct Exit {
+    pub status: ExitStatus,
+    /// Whether it was killed for running past its time limit.
+    pub timed_out: bool,
+    /// Peak resident memory in bytes, where the platform reports it.
+    pub peak_memory: Option<usize>,
+}
+
+/// Kills a process once its time limit elapsed, unless stopped before.
+pub struct Watchdog {
+    stop: mpsc::Sender<()>,
+    handle: JoinHandle<bool>,
+}
+
+impl Watchdog {
+    pub fn start(child: &Child, time_limit: Duration) -> Self {
+        let pid = child.id();
+        let (stop, stopped) = mpsc::channel();
+
+        let handle = thread::spawn(move || match stopped.recv_timeout(time_limit) {
+            Err(RecvTimeoutError::Timeout) => {
+                kill(pid);
+                true
+            }
+            _ => false,
+        });
+
+        Self { stop, handle }
+    }
+
+    /// Stops watching and returns whether the process was killed.
+    fn stop(self) -> bool {
+        let _ = self.stop.send(());
+        self.handle.join().unwrap_or(false)
+    }
+}
+
+fn kill(pid: u32) {
+    #[cfg(target_os = "linux")]
+    linux::kill(pid);
+
+    // Elsewhere the run can only be judged once it is over.
+    #[cfg(not(target_os = "linux"))]
+    let _ = pid;
+}
+
+/// Waits for `child` to exit. On Linux the watchdog is stopped before the
+/// process is reaped, so it can never kill another process reusing its id.
+/// `child` is consumed, as it must not be waited on or killed once reaped.
+pub fn wait(child: Child, watchdog: Option<Watchdog>) -> io::Result<Exit> {
+    #[cfg(target_os = "linux")]
+    return linux::wait(child, watchdog);
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let mut child = child;
+        let status = child.wait()?;
+
+        Ok(Exit {
+            status,
+            timed_out: watchdog.is_some_and(Watchdog::stop),
+            peak_memory: None,
+        })
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::{Exit, Watchdog};
+    use std::io;
+    use std::mem;
+    use std::os::unix::process::ExitStatusExt;
+    use std::process::{Child, ExitStatus};
+
+    pub fn kill(pid: u32) {
+        // SAFETY: the process isn't reaped before the watchdog is stopped, so
+        // `pid` still refers to it.
+        unsafe {
+            libc::kill(pid as libc::pid_t, libc::SIGKILL);
+        }
+    }
+
+    /// Retries `call` while it is interrupted by a signal.
+    fn retry(mut call: impl FnMut() -> libc::c_int) -> io::Result<libc::c_int> {
+        loop {
+            match call() {
+                -1 if io::Error::last_os_error().kind() == io::ErrorKind::Interrupted => {}
+                -1 => return Err(io::Error::last_os_error()),
+                result => return Ok(result),
+            }
+        }
+    }
+
+    pub fn wait(child: Child, watchdog: Option<Watchdog>) -> io::Result<Exit> {
+        let pid = child.id() as libc::pid_t;
+
+        // SAFETY: plain system calls on zero-initialized C structs.
+        unsafe {
+            let mut info: libc::siginfo_t = mem::zeroed();
+            retry(|| {
+                libc::waitid(
+                    libc::P_PID,
+                    pid as libc::id_t,
+                    &mut info,
+                    libc::WEXITED | libc::WNOWAIT,
+                )
+            })?;
+
+            let timed_out = watchdog.is_some_and(Watchdog::stop);
+
+            // Reaping behind the back of `Child` keeps the peak memory of this
+            // process alone, where `getrusage(RUSAGE_CHILDREN)` after
+            // `Child::wait` would report the largest of every child so far.
+            // `Child` doesn't know it is reaped, so it is dropped right here:
+            // waiting on or killing it afterwards could hit a process reusing
+            // its id. Its pipes were taken or are closed on drop.
+            let mut status = 0;
+            let mut usage: libc::rusage = mem::zeroed();
+            retry(|| libc::wait4(pid, &mut status, 0, &mut usage))?;
+            drop(child);
+
+            Ok(Exit {
+                status: ExitStatus::from_raw(status),
+                timed_out,
+                // Reported in kilobytes.
+                peak_memory: Some(usage.ru_maxrss as usize * 1024),
+            })
+        }
+    }
+}
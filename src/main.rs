@@ -1,14 +1,74 @@
 use colored::Colorize;
-use easy_runner::{execute, log};
+use easy_runner::cache_file::{IoFiles, RunArgs, TestLabels};
+use easy_runner::execute::{self, RunError};
+use easy_runner::log;
+use easy_runner::selector_evaluator::parse_label;
+use easy_runner::utils::{parse_duration, parse_env_var, parse_size};
 
-use clap::{Parser, Subcommand};
-use std::{fs, path::PathBuf};
+use clap::{Args, Parser, Subcommand};
+use std::{fs, path::PathBuf, process, time::Duration};
+
+#[derive(Debug, Args)]
+struct RunArgsOptions {
+    #[arg(
+        long = "arg",
+        allow_hyphen_values = true,
+        help = "Argument passed to the binary, may be repeated"
+    )]
+    args: Vec<String>,
+
+    #[arg(
+        long = "env",
+        value_parser = parse_env_var,
+        help = "Environment variable as KEY=VALUE, may be repeated"
+    )]
+    env: Vec<(String, String)>,
+}
+
+impl From<RunArgsOptions> for RunArgs {
+    fn from(options: RunArgsOptions) -> Self {
+        RunArgs {
+            args: options.args,
+            env: options.env.into_iter().collect(),
+            ..RunArgs::default()
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+struct LabelOptions {
+    #[arg(long, value_parser = parse_label, help = "Name to select the test by")]
+    name: Option<String>,
+
+    #[arg(
+        long = "tag",
+        value_parser = parse_label,
+        help = "Tag to select the test by, may be repeated"
+    )]
+    tags: Vec<String>,
+}
+
+impl From<LabelOptions> for TestLabels {
+    fn from(options: LabelOptions) -> Self {
+        TestLabels {
+            name: options.name,
+            tags: options.tags,
+        }
+    }
+}
 
 #[derive(Debug, Subcommand)]
 enum CommandTest {
     Add {
         input: String,
-        output: String,
+        #[arg(help = "Expected output, omit to only check that the program runs fine")]
+        output: Option<String>,
+
+        #[command(flatten)]
+        run_args: RunArgsOptions,
+
+        #[command(flatten)]
+        labels: LabelOptions,
     },
     AddLink {
         #[arg(help = "Path to the tests")]
@@ -19,22 +79,88 @@ enum CommandTest {
 
         #[arg(help = "Output path, required when --standalone is used")]
         output: Option<PathBuf>,
+
+        #[command(flatten)]
+        run_args: RunArgsOptions,
+
+        #[command(flatten)]
+        labels: LabelOptions,
     },
     RunAt {
         expression: String,
 
+        #[arg(long, short, help = "Build profile to use, e.g. release")]
+        profile: Option<String>,
+
         #[arg(long, short, help = "Force recompilation of the project")]
         force_recompile: bool,
 
         #[arg(long, short, help = "Show full result of the process.")]
         show_full: bool,
+
+        #[arg(long, value_parser = parse_size, help = "Bytes a test may print, e.g. 16M")]
+        output_limit: Option<usize>,
+
+        #[arg(long, help = "Stop at the first failing test")]
+        first_failure: bool,
+    },
+    IoFiles {
+        #[arg(
+            requires = "output",
+            help = "File the solution reads, e.g. input.txt. Omit both to use stdin and stdout"
+        )]
+        input: Option<String>,
+
+        #[arg(help = "File the solution writes, e.g. output.txt")]
+        output: Option<String>,
+    },
+    OutputLimit {
+        #[arg(
+            value_parser = parse_size,
+            help = "Bytes a test may print, e.g. 16M. Omit to restore the default"
+        )]
+        limit: Option<usize>,
+    },
+    TimeLimit {
+        #[arg(
+            value_parser = parse_duration,
+            help = "Time a test may take, e.g. 2s or 500ms. Omit to remove the limit"
+        )]
+        limit: Option<Duration>,
+    },
+    MemoryLimit {
+        #[arg(
+            value_parser = parse_size,
+            help = "Memory a test may use, e.g. 256M. Omit to remove the limit"
+        )]
+        limit: Option<usize>,
+    },
+    Lint,
+    Bless {
+        #[arg(help = "Tests to bless, every test without an expected output when omitted")]
+        expression: Option<String>,
+
+        #[arg(long, short, help = "Build profile to use, e.g. release")]
+        profile: Option<String>,
     },
     Run {
+        #[arg(long, short, help = "Build profile to use, e.g. release")]
+        profile: Option<String>,
+
         #[arg(long, short, help = "Force recompilation of the project")]
         force_recompile: bool,
 
         #[arg(long, short, help = "Show full result of the process.")]
         show_full: bool,
+
+        #[arg(long, value_parser = parse_size, help = "Bytes a test may print, e.g. 16M")]
+        output_limit: Option<usize>,
+
+        #[arg(long, help = "Only rerun the tests that failed last time")]
+        failed: bool,
+
+        #[arg(long, help = "Stop at the first failing test")]
+        first_failure: bool,
     },
 }
 
@@ -46,6 +172,19 @@ enum CommandCache {
         #[arg(long, short, help = "Force recompilation of all registered file")]
         all: bool,
     },
+    Relink {
+        #[arg(help = "Path prefix currently stored in the cache")]
+        old_prefix: PathBuf,
+
+        #[arg(help = "Path prefix to replace it with")]
+        new_prefix: PathBuf,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+enum CommandSandbox {
+    Enable,
+    Disable,
 }
 
 #[derive(Debug, Subcommand)]
@@ -58,8 +197,94 @@ enum Command {
 
     Run {
         path: PathBuf,
+        #[arg(long, short, help = "Build profile to use, e.g. release")]
+        profile: Option<String>,
         #[arg(long, short, help = "Force recompilation of the project")]
         force_recompile: bool,
+        #[command(flatten)]
+        run_args: RunArgsOptions,
+    },
+
+    Bundle {
+        path: PathBuf,
+        #[arg(
+            long,
+            short,
+            help = "Compile the bundle and rerun the tests against it"
+        )]
+        verify: bool,
+        #[arg(long, short, help = "Build profile to verify with, e.g. release")]
+        profile: Option<String>,
+    },
+
+    TestAll {
+        #[arg(long, short, help = "Only test files whose binary is stale")]
+        changed_only: bool,
+        #[arg(long, short, help = "Build profile to use, e.g. release")]
+        profile: Option<String>,
+        #[arg(long, value_parser = parse_size, help = "Bytes a test may print, e.g. 16M")]
+        output_limit: Option<usize>,
+    },
+
+    Bench {
+        path: PathBuf,
+        #[arg(help = "Tests to benchmark, every test when omitted")]
+        expression: Option<String>,
+        #[arg(
+            long,
+            short,
+            default_value_t = 10,
+            value_parser = clap::value_parser!(u32).range(1..),
+            help = "Measured runs of each test"
+        )]
+        runs: u32,
+        #[arg(long, short, default_value_t = 1, help = "Unmeasured runs before them")]
+        warmup: u32,
+        #[arg(long, short, help = "Build profile to use, e.g. release")]
+        profile: Option<String>,
+        #[arg(long, short, help = "Another source file to compare with")]
+        against: Option<PathBuf>,
+        #[arg(long, help = "Another build profile to compare with")]
+        against_profile: Option<String>,
+        #[arg(long, value_parser = parse_size, help = "Bytes a test may print, e.g. 16M")]
+        output_limit: Option<usize>,
+    },
+
+    DiffRun {
+        path: PathBuf,
+        #[arg(help = "Solution to compare, run on the tests of the first one")]
+        other_path: PathBuf,
+        #[arg(help = "Tests to compare on, every test when omitted")]
+        expression: Option<String>,
+        #[arg(long, short, help = "Build profile to use, e.g. release")]
+        profile: Option<String>,
+        #[arg(long, value_parser = parse_size, help = "Bytes a test may print, e.g. 16M")]
+        output_limit: Option<usize>,
+    },
+
+    History {
+        path: PathBuf,
+        #[arg(long, short, help = "Build profile the runs were made with")]
+        profile: Option<String>,
+        #[arg(
+            long,
+            default_value_t = 10.0,
+            help = "Slowdown in percent highlighted as a regression"
+        )]
+        threshold: f64,
+    },
+
+    Convert {
+        #[arg(help = "Test file to convert")]
+        path: PathBuf,
+        #[arg(help = "Expected output file linked with it")]
+        expected_output: Option<PathBuf>,
+        #[arg(
+            long,
+            short,
+            help = "File to write, <name>.tests next to it by default"
+        )]
+        output: Option<PathBuf>,
     },
 
     Status,
@@ -68,6 +293,10 @@ enum Command {
         #[command(subcommand)]
         command: CommandCache,
     },
+    Sandbox {
+        #[command(subcommand)]
+        command: CommandSandbox,
+    },
 }
 
 #[derive(Debug, Parser)]
@@ -76,131 +305,295 @@ struct Cli {
     command: Command,
 }
 
+/// Checks that a path given on the command line exists and canonicalizes it.
+fn existing_path(path: PathBuf) -> Result<PathBuf, RunError> {
+    if !path.exists() {
+        return Err(RunError::Usage(format!(
+            "File {path:?} not found. Please make sure it is a valid filename."
+        )));
+    }
+
+    Ok(fs::canonicalize(path)?)
+}
+
 fn main() {
-    let current_dir = std::env::current_dir().expect("Cannot find current directory.");
-    let args = Cli::parse();
+    // clap exits with 2 on usage errors, which is reserved for compilation errors.
+    let args = Cli::try_parse().unwrap_or_else(|err| {
+        let _ = err.print();
+        process::exit(if err.use_stderr() { 3 } else { 0 });
+    });
+
+    match run(args) {
+        Ok(exit_code) => process::exit(exit_code),
+        Err(err) => {
+            log!(error, "{err}");
+            process::exit(err.exit_code());
+        }
+    }
+}
+
+/// Runs the parsed command and returns the process exit code.
+fn run(args: Cli) -> Result<i32, RunError> {
+    let current_dir = std::env::current_dir()?;
 
     if !execute::check_initialized(&current_dir) && !matches!(args.command, Command::Init) {
-        println!(
-            "{} {} {} {}",
-            "Error:".red().bold(),
-            "Project is not initialized. Please run".white(),
-            "\"erunner init\"".yellow().bold(),
-            "to initialize the project.".white()
-        );
-
-        return;
+        return Err(RunError::Config(
+            "Project is not initialized. Please run \"erunner init\" to initialize the project."
+                .to_string(),
+        ));
     }
 
     match args.command {
         Command::Test { path, command } => {
-            if !path.exists() {
-                log!(
-                    error,
-                    "File {path:?} not found. Please make sure it is a valid filename."
-                );
-                return;
-            }
-
-            let path = fs::canonicalize(path).expect("Unable to canonicalize path");
+            let path = existing_path(path)?;
 
             match command {
-                CommandTest::Add { input, output } => {
-                    execute::test::add(&path, &input, &output).expect("Failed to add test.");
+                CommandTest::Add {
+                    input,
+                    output,
+                    run_args,
+                    labels,
+                } => {
+                    execute::test::add(
+                        &path,
+                        &input,
+                        output.as_deref(),
+                        run_args.into(),
+                        labels.into(),
+                    )?;
                 }
 
                 CommandTest::AddLink {
                     tests,
                     standalone,
                     output,
+                    run_args,
+                    labels,
                 } => {
                     if standalone {
                         // Handle standalone case where both input and output paths are needed
-                        if let Some(output) = output {
-                            // Check if both input and output files exist
-                            if !tests.exists() || !output.exists() {
-                                log!(
-                                    error,
-                                    "Input content file is {}, {} output content file {}, Please make sure both of them are valid filenames.",
-                                    if tests.exists() { "exists" } else { "does not exist" },
-                                    if !tests.exists() && !output.exists() { "And" } else { "But" },
-                                    if output.exists() { "exists" } else { "does not exist" },
-                                );
-                                return;
-                            }
-
-                            let input =
-                                fs::canonicalize(tests).expect("Unable to canonicalize input path");
-                            let output = fs::canonicalize(output)
-                                .expect("Unable to canonicalize output path");
-
-                            // Execute standalone linking logic
-                            execute::test::add_standalone_file_link(&path, &input, &output)
-                                .expect("Failed to add Linked test.");
-                        } else {
-                            // If `--standalone` is used but output is missing, show an error
-                            eprintln!("Error: --standalone requires an output path.");
+                        let Some(output) = output else {
+                            return Err(RunError::Usage(
+                                "--standalone requires an output path.".to_string(),
+                            ));
+                        };
+
+                        // Check if both input and output files exist
+                        if !tests.exists() || !output.exists() {
+                            return Err(RunError::Usage(format!(
+                                "Input content file is {}, {} output content file {}, Please make sure both of them are valid filenames.",
+                                if tests.exists() { "exists" } else { "does not exist" },
+                                if !tests.exists() && !output.exists() { "And" } else { "But" },
+                                if output.exists() { "exists" } else { "does not exist" },
+                            )));
                         }
+
+                        let input = fs::canonicalize(tests)?;
+                        let output = fs::canonicalize(output)?;
+
+                        // Execute standalone linking logic
+                        execute::test::add_standalone_file_link(
+                            &path,
+                            &input,
+                            &output,
+                            run_args.into(),
+                            labels.into(),
+                        )?;
                     } else {
                         // Regular AddLink case where only the `tests` path is needed
-                        if !tests.exists() {
-                            log!(error, "Test file {:?} doesn't exist.", tests);
-                            return;
-                        }
-
-                        let tests =
-                            fs::canonicalize(tests).expect("Unable to canonicalize tests path");
+                        let tests = existing_path(tests)?;
 
                         // Execute regular linking logic
-                        execute::test::add_file_link(&path, &tests)
-                            .expect("Failed to add Linked test.");
+                        execute::test::add_file_link(
+                            &path,
+                            &tests,
+                            run_args.into(),
+                            labels.into(),
+                        )?;
                     }
                 }
 
+                CommandTest::IoFiles { input, output } => {
+                    let io_files = input
+                        .zip(output)
+                        .map(|(input, output)| IoFiles { input, output });
+                    execute::test::set_io_files(&path, io_files)?;
+                }
+
+                CommandTest::OutputLimit { limit } => {
+                    execute::test::set_output_limit(&path, limit)?;
+                }
+
+                CommandTest::TimeLimit { limit } => {
+                    execute::test::set_time_limit(&path, limit)?;
+                }
+
+                CommandTest::MemoryLimit { limit } => {
+                    execute::test::set_memory_limit(&path, limit)?;
+                }
+
+                CommandTest::Lint => execute::test::lint(&path)?,
+
+                CommandTest::Bless {
+                    expression,
+                    profile,
+                } => {
+                    execute::bless::bless(&path, expression.as_deref(), profile.as_deref())?;
+                }
+
                 CommandTest::RunAt {
                     expression,
+                    profile,
                     force_recompile,
                     show_full,
+                    output_limit,
+                    first_failure,
                 } => {
-                    execute::test::run_at(&path, &expression, force_recompile, show_full)
-                        .expect("Failed to run test-at index.");
+                    let outcome = execute::test::run_at(
+                        &path,
+                        &expression,
+                        profile.as_deref(),
+                        force_recompile,
+                        show_full,
+                        output_limit,
+                        first_failure,
+                    )?;
+                    return Ok(outcome.exit_code());
                 }
                 CommandTest::Run {
+                    profile,
                     force_recompile,
                     show_full,
+                    output_limit,
+                    failed,
+                    first_failure,
                 } => {
-                    execute::test::run(&path, force_recompile, show_full)
-                        .expect("Failed to run executable.");
+                    let outcome = execute::test::run(
+                        &path,
+                        profile.as_deref(),
+                        force_recompile,
+                        show_full,
+                        output_limit,
+                        failed,
+                        first_failure,
+                    )?;
+                    return Ok(outcome.exit_code());
                 }
             }
         }
 
         Command::Run {
             path,
+            profile,
             force_recompile,
+            run_args,
         } => {
-            if !path.exists() {
-                log!(
-                    error,
-                    "File {path:?} not found. Please make sure it is a valid filename."
-                );
-                return;
-            }
+            let path = existing_path(path)?;
+            let outcome =
+                execute::run(&path, profile.as_deref(), force_recompile, &run_args.into())?;
+            return Ok(outcome.exit_code());
+        }
+
+        Command::Bundle {
+            path,
+            verify,
+            profile,
+        } => {
+            let path = existing_path(path)?;
+            let outcome = execute::bundle::bundle(&path, verify, profile.as_deref())?;
+            return Ok(outcome.exit_code());
+        }
+
+        Command::TestAll {
+            changed_only,
+            profile,
+            output_limit,
+        } => {
+            let outcome = execute::test::run_all(changed_only, profile.as_deref(), output_limit)?;
+            return Ok(outcome.exit_code());
+        }
+
+        Command::Bench {
+            path,
+            expression,
+            runs,
+            warmup,
+            profile,
+            against,
+            against_profile,
+            output_limit,
+        } => {
+            let path = existing_path(path)?;
+            let against = against.map(existing_path).transpose()?;
+            let outcome = execute::bench::bench(
+                &path,
+                expression.as_deref(),
+                profile.as_deref(),
+                against.as_deref(),
+                against_profile.as_deref(),
+                runs as usize,
+                warmup as usize,
+                output_limit,
+            )?;
+            return Ok(outcome.exit_code());
+        }
 
-            let path = fs::canonicalize(path).expect("Unable to canonicalize path");
-            execute::run(&path, force_recompile).expect("Failed to the run file.");
+        Command::DiffRun {
+            path,
+            other_path,
+            expression,
+            profile,
+            output_limit,
+        } => {
+            let path = existing_path(path)?;
+            let other_path = existing_path(other_path)?;
+            let outcome = execute::diff_run::diff_run(
+                &path,
+                &other_path,
+                expression.as_deref(),
+                profile.as_deref(),
+                output_limit,
+            )?;
+            return Ok(outcome.exit_code());
+        }
+
+        Command::History {
+            path,
+            profile,
+            threshold,
+        } => {
+            let path = existing_path(path)?;
+            execute::history::history(&path, profile.as_deref(), threshold)?;
         }
 
-        Command::Status => execute::status().expect("Failed to show status."),
-        Command::Init => {
-            execute::initialize(&current_dir).expect("Failed to initialize erunner cache.")
+        Command::Convert {
+            path,
+            expected_output,
+            output,
+        } => {
+            let path = existing_path(path)?;
+            let expected_output = expected_output.map(existing_path).transpose()?;
+            execute::test::convert(&path, expected_output.as_ref(), output.as_deref())?;
         }
 
+        Command::Status => execute::status()?,
+        Command::Init => execute::initialize(&current_dir)?,
+
         Command::Cache { command } => match command {
-            CommandCache::Clean => execute::cache::clean().expect("Failed to clean erunner cache."),
-            CommandCache::Purge => execute::cache::purge().expect("Failed to purge erunner cache."),
-            CommandCache::Recompile { all } => execute::cache::recompile(all)
-                .expect("Failed to recompile erunner registered files."),
+            CommandCache::Clean => execute::cache::clean()?,
+            CommandCache::Purge => execute::cache::purge()?,
+            CommandCache::Recompile { all } => execute::cache::recompile(all)?,
+            CommandCache::Relink {
+                old_prefix,
+                new_prefix,
+            } => execute::cache::relink(&old_prefix, &new_prefix)?,
+        },
+
+        Command::Sandbox { command } => match command {
+            CommandSandbox::Enable => execute::set_sandbox(true)?,
+            CommandSandbox::Disable => execute::set_sandbox(false)?,
         },
     }
+
+    Ok(0)
 }
@@ -0,0 +1,223 @@
+use data_encoding::HEXLOWER;
+use ring::rand::{SecureRandom, SystemRandom};
+use std::env;
+use std::fs;
+use std::io;
+#[cfg(unix)]
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+
+/// Processes a sandboxed run may start on top of the ones the user already runs.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+const PROCESS_LIMIT: u64 = 64;
+
+/// A fresh directory under the system temp directory, removed on drop.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    /// Attempts at a fresh random name before giving up.
+    const ATTEMPTS: usize = 16;
+
+    /// The name is random and the directory only accessible to the user, so
+    /// other users can neither predict nor tamper with it.
+    pub fn new() -> io::Result<Self> {
+        let random = SystemRandom::new();
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        builder.mode(0o700);
+
+        for _ in 0..Self::ATTEMPTS {
+            let mut suffix = [0u8; 8];
+            random
+                .fill(&mut suffix)
+                .map_err(|_| io::Error::other("Unable to generate a random directory name."))?;
+
+            let path = env::temp_dir().join(format!("erunner-{}", HEXLOWER.encode(&suffix)));
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self { path }),
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            "Unable to find an unused temporary directory name.",
+        ))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Spawns `command` inside `working_dir` with a cleared environment. On Linux
+/// it also gets no network and a limited number of processes, as far as the
+/// kernel allows it. Only `PATH` and variables set on `command` itself are
+/// kept, so interpreters can still be found and tests keep their overrides.
+pub fn spawn_isolated(command: &mut Command, working_dir: &Path) -> io::Result<Child> {
+    let path = env::var_os("PATH").unwrap_or_default();
+    let overrides: Vec<_> = command
+        .get_envs()
+        .filter_map(|(key, value)| Some((key.to_owned(), value?.to_owned())))
+        .collect();
+
+    command
+        .current_dir(working_dir)
+        .env_clear()
+        .env("PATH", path)
+        .env("HOME", working_dir)
+        .env("TMPDIR", working_dir)
+        .envs(overrides);
+
+    #[cfg(target_os = "linux")]
+    return linux::spawn(command);
+
+    #[cfg(not(target_os = "linux"))]
+    command.spawn()
+}
+
+/// Whether sandboxed runs can be cut off from the network on this machine.
+pub fn network_isolation_supported() -> bool {
+    #[cfg(target_os = "linux")]
+    return linux::network_isolation_supported();
+
+    #[cfg(not(target_os = "linux"))]
+    false
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use super::PROCESS_LIMIT;
+    use std::ffi::CString;
+    use std::fs;
+    use std::io;
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command};
+    use std::sync::OnceLock;
+
+    /// Unprivileged user namespaces can be disabled, in which case only root
+    /// may create a network namespace.
+    fn namespace_flags() -> Option<libc::c_int> {
+        static FLAGS: OnceLock<Option<libc::c_int>> = OnceLock::new();
+
+        *FLAGS.get_or_init(|| {
+            [libc::CLONE_NEWUSER | libc::CLONE_NEWNET, libc::CLONE_NEWNET]
+                .into_iter()
+                .find(|&flags| can_unshare(flags))
+        })
+    }
+
+    pub fn network_isolation_supported() -> bool {
+        namespace_flags().is_some()
+    }
+
+    /// Tries `unshare(flags)` in a throwaway child, as it can't be undone
+    /// in this process.
+    fn can_unshare(flags: libc::c_int) -> bool {
+        // SAFETY: the child only makes raw system calls before exiting.
+        unsafe {
+            match libc::fork() {
+                -1 => false,
+                0 => libc::_exit(if libc::unshare(flags) == 0 { 0 } else { 1 }),
+                child => {
+                    let mut status = 0;
+                    libc::waitpid(child, &mut status, 0) == child
+                        && libc::WIFEXITED(status)
+                        && libc::WEXITSTATUS(status) == 0
+                }
+            }
+        }
+    }
+
+    /// RLIMIT_NPROC counts every process of the user, not only the sandboxed
+    /// ones. They are counted once per invocation, as scanning `/proc` before
+    /// every run of every test adds up.
+    fn process_limit(uid: libc::uid_t) -> u64 {
+        static LIMIT: OnceLock<u64> = OnceLock::new();
+
+        *LIMIT.get_or_init(|| {
+            let user_processes = fs::read_dir("/proc")
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .filter(|entry| entry.file_name().to_string_lossy().parse::<u32>().is_ok())
+                        .filter(|entry| {
+                            entry.metadata().is_ok_and(|metadata| metadata.uid() == uid)
+                        })
+                        .count() as u64
+                })
+                .unwrap_or_default();
+
+            user_processes + PROCESS_LIMIT
+        })
+    }
+
+    /// Writes `content` to `path` using only raw system calls.
+    ///
+    /// # Safety
+    /// Must only be called between fork and exec.
+    unsafe fn write_proc_file(path: &CString, content: &CString) {
+        let fd = libc::open(path.as_ptr(), libc::O_WRONLY);
+        if fd >= 0 {
+            libc::write(fd, content.as_ptr().cast(), content.as_bytes().len());
+            libc::close(fd);
+        }
+    }
+
+    pub fn spawn(command: &mut Command) -> io::Result<Child> {
+        // SAFETY: getuid and getgid can't fail.
+        let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+        let process_limit = process_limit(uid);
+        let flags = namespace_flags();
+
+        // Everything the child needs is allocated here, as allocating after
+        // fork isn't safe.
+        let setgroups = CString::new("/proc/self/setgroups")?;
+        let uid_map = CString::new("/proc/self/uid_map")?;
+        let gid_map = CString::new("/proc/self/gid_map")?;
+        let deny = CString::new("deny")?;
+        let uid_mapping = CString::new(format!("{uid} {uid} 1"))?;
+        let gid_mapping = CString::new(format!("{gid} {gid} 1"))?;
+
+        // SAFETY: the closure only makes raw system calls on preallocated data.
+        unsafe {
+            command.pre_exec(move || {
+                if let Some(flags) = flags {
+                    if libc::unshare(flags) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+
+                    // Keep the same ids inside the new user namespace.
+                    if flags & libc::CLONE_NEWUSER != 0 {
+                        write_proc_file(&setgroups, &deny);
+                        write_proc_file(&uid_map, &uid_mapping);
+                        write_proc_file(&gid_map, &gid_mapping);
+                    }
+                }
+
+                let limit = libc::rlimit {
+                    rlim_cur: process_limit,
+                    rlim_max: process_limit,
+                };
+                if libc::setrlimit(libc::RLIMIT_NPROC, &limit) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(())
+            });
+        }
+
+        command.spawn()
+    }
+}
@@ -0,0 +1,655 @@
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::cache_file::{RunArgs, TestLabels};
+use crate::selector_evaluator::parse_label;
+use crate::test_file::{Encoding, Location, ParseError, SimpleTest, TestInput};
+use crate::utils::{parse_duration, parse_env_var, parse_size};
+
+/// First line marking a test file of any extension as a section file.
+pub const SECTION_FILE_HEADER: &str = "#format: v2";
+/// Extension of section files, which need no header.
+pub const SECTION_FILE_EXTENSION: &str = "tests";
+
+const SECTION_PREFIX: &[u8] = b"===";
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Input,
+    Output,
+    Args,
+    Env,
+    Limits,
+}
+
+impl Section {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "input" => Some(Self::Input),
+            "output" => Some(Self::Output),
+            "args" => Some(Self::Args),
+            "env" => Some(Self::Env),
+            "limits" => Some(Self::Limits),
+            _ => None,
+        }
+    }
+}
+
+/// Whether `path` is a section file rather than a brace delimited one.
+pub fn is_section_file(path: &Path) -> io::Result<bool> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == SECTION_FILE_EXTENSION)
+    {
+        return Ok(true);
+    }
+
+    let mut first_line = Vec::new();
+    BufReader::new(File::open(path)?).read_until(b'\n', &mut first_line)?;
+
+    Ok(first_line.trim_ascii() == SECTION_FILE_HEADER.as_bytes())
+}
+
+/// Reads a line oriented test file. Every case starts with an
+/// `=== input [name] [@tag...]` line, optionally followed by `=== output`,
+/// `=== args`, `=== env` and `=== limits` sections. Section data is taken
+/// verbatim, only lines starting with `===` after any backslashes lose one
+/// leading backslash. Lines before the first case are comments. Inputs and
+/// outputs without such lines are left in the file, see `TestInput::File`.
+pub struct SectionFileIterator {
+    path: Arc<Path>,
+    reader: BufReader<File>,
+    line: Vec<u8>,
+    line_number: usize,
+    /// Bytes read so far, where the next line starts.
+    offset: u64,
+    /// Header line ending the previous case, with its line number.
+    next_header: Option<(usize, String)>,
+    done: bool,
+}
+
+impl SectionFileIterator {
+    pub fn new(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let file = File::open(path)?;
+
+        Ok(Self {
+            path: Arc::from(path),
+            reader: BufReader::with_capacity(32 * 1024, file),
+            line: Vec::new(),
+            line_number: 0,
+            offset: 0,
+            next_header: None,
+            done: false,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Reads the next line, false at the end of the file.
+    fn read_line(&mut self) -> io::Result<bool> {
+        self.line.clear();
+        if self.reader.read_until(b'\n', &mut self.line)? == 0 {
+            return Ok(false);
+        }
+        self.line_number += 1;
+        self.offset += self.line.len() as u64;
+
+        Ok(true)
+    }
+
+    /// Bytes of the file from `start` up to `end`, read again from the disk.
+    fn read_span(&self, start: u64, end: u64) -> io::Result<Vec<u8>> {
+        let mut data = Vec::new();
+        self.span(start, end).reader()?.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn span(&self, start: u64, end: u64) -> TestInput {
+        TestInput::File {
+            path: self.path.clone(),
+            offset: start,
+            length: end - start,
+            encoding: Encoding::Verbatim,
+        }
+    }
+
+    fn header(&self) -> Option<String> {
+        let line = self.line.strip_suffix(b"\n").unwrap_or(&self.line);
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+        line.starts_with(SECTION_PREFIX)
+            .then(|| String::from_utf8_lossy(&line[SECTION_PREFIX.len()..]).into_owned())
+    }
+
+    /// Error at `offset` in the header line `header`, given without `===`.
+    fn header_error(
+        &self,
+        line_number: usize,
+        header: &str,
+        offset: usize,
+        message: String,
+    ) -> Box<dyn Error> {
+        let line = [SECTION_PREFIX, header.as_bytes()].concat();
+        let location = Location::new(line_number, &line, SECTION_PREFIX.len() + offset);
+
+        Box::new(ParseError::new(&self.path, location, message))
+    }
+
+    fn read_case(&mut self) -> Result<Option<SimpleTest>, Box<dyn Error>> {
+        let (mut line_number, mut header) = match self.next_header.take() {
+            Some(header) => header,
+            None => loop {
+                if !self.read_line()? {
+                    return Ok(None);
+                }
+                if let Some(header) = self.header() {
+                    break (self.line_number, header);
+                }
+            },
+        };
+
+        let mut test = SimpleTest::new();
+        let mut seen = Vec::new();
+        let mut data = Vec::new();
+
+        loop {
+            let section = self.parse_header(&header, line_number, &mut test.labels)?;
+            let name_offset = header.len() - header.trim_start().len();
+            if seen.is_empty() && section != Section::Input {
+                return Err(self.header_error(
+                    line_number,
+                    &header,
+                    name_offset,
+                    "Expected an \"=== input\" section, every case starts with one.".to_string(),
+                ));
+            }
+            if seen.contains(&section) {
+                return Err(self.header_error(
+                    line_number,
+                    &header,
+                    name_offset,
+                    format!("Repeated section \"{}\" in the same case.", header.trim()),
+                ));
+            }
+            seen.push(section);
+
+            data.clear();
+            let start = self.offset;
+            // Where the data read so far ends, without its last line ending.
+            let mut end = start;
+            let mut verbatim = matches!(section, Section::Input | Section::Output);
+
+            let next_header = loop {
+                if !self.read_line()? {
+                    break None;
+                }
+                if let Some(header) = self.header() {
+                    break Some((self.line_number, header));
+                }
+
+                if verbatim && !is_escaped_header(&self.line) {
+                    let line = self.line.strip_suffix(b"\n").unwrap_or(&self.line);
+                    let line = line.strip_suffix(b"\r").unwrap_or(line);
+                    end = self.offset - (self.line.len() - line.len()) as u64;
+                    continue;
+                }
+                if verbatim {
+                    // Lines before the escaped one were taken verbatim.
+                    data = self.read_span(start, self.offset - self.line.len() as u64)?;
+                    verbatim = false;
+                }
+                data.extend_from_slice(unescape(&self.line));
+            };
+
+            if verbatim {
+                match section {
+                    Section::Input => test.input = self.span(start, end),
+                    _ => test.expected_output = Some(self.span(start, end)),
+                }
+            } else {
+                // The line ending before the next header belongs to it.
+                if data.ends_with(b"\n") {
+                    data.pop();
+                    if data.ends_with(b"\r") {
+                        data.pop();
+                    }
+                }
+                self.apply_section(&mut test, section, &data, line_number)?;
+            }
+
+            match next_header {
+                Some((next_line_number, next_header))
+                    if next_header.split_whitespace().next() != Some("input") =>
+                {
+                    (line_number, header) = (next_line_number, next_header);
+                }
+                next_header => {
+                    self.next_header = next_header;
+                    return Ok(Some(test));
+                }
+            }
+        }
+    }
+
+    /// Parses `header`, the part of a section line after `===`, setting the
+    /// labels given to an input section.
+    fn parse_header(
+        &self,
+        header: &str,
+        line_number: usize,
+        labels: &mut TestLabels,
+    ) -> Result<Section, Box<dyn Error>> {
+        let error = |word: &str, message: String| {
+            let offset = word.as_ptr() as usize - header.as_ptr() as usize;
+            self.header_error(line_number, header, offset, message)
+        };
+
+        if !header.is_empty() && !header.starts_with(char::is_whitespace) {
+            return Err(error(header, "Expected a space after \"===\".".to_string()));
+        }
+
+        let mut words = header.split_whitespace();
+        let name = words.next().unwrap_or(&header[header.len()..]);
+        let section = Section::from_name(name).ok_or_else(|| {
+            error(
+                name,
+                format!("Unknown section \"{name}\", expected input, output, args, env or limits."),
+            )
+        })?;
+
+        for word in words {
+            if section != Section::Input {
+                return Err(error(
+                    word,
+                    format!(
+                        "Unexpected \"{word}\" after \"{name}\", only inputs take a name and tags."
+                    ),
+                ));
+            }
+
+            match word.strip_prefix('@') {
+                Some(tag) => labels.tags.push(
+                    parse_label(tag).map_err(|err| error(word, format!("Invalid tag: {err}")))?,
+                ),
+                None if labels.name.is_none() => {
+                    labels.name = Some(
+                        parse_label(word)
+                            .map_err(|err| error(word, format!("Invalid name: {err}")))?,
+                    )
+                }
+                None => {
+                    return Err(error(
+                        word,
+                        format!("Unexpected \"{word}\", an input has a single name."),
+                    ))
+                }
+            }
+        }
+
+        Ok(section)
+    }
+
+    /// Stores `data`, the content of the section whose header is at
+    /// `line_number`, into `test`.
+    fn apply_section(
+        &self,
+        test: &mut SimpleTest,
+        section: Section,
+        data: &[u8],
+        line_number: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        // Lines of the section, without line endings and blank ones.
+        let lines = || {
+            (line_number + 1..)
+                .zip(data.split(|&byte| byte == b'\n'))
+                .map(|(line_number, line)| (line_number, line.strip_suffix(b"\r").unwrap_or(line)))
+                .filter(|(_, line)| !line.trim_ascii().is_empty())
+        };
+        let error = |line_number: usize, line: &[u8], message: String| -> Box<dyn Error> {
+            let offset = line.len() - line.trim_ascii_start().len();
+            Box::new(ParseError::new(
+                &self.path,
+                Location::new(line_number, line, offset),
+                message,
+            ))
+        };
+
+        match section {
+            Section::Input => test.input = data.to_vec().into(),
+            Section::Output => test.expected_output = Some(data.to_vec().into()),
+            Section::Args => {
+                test.run_args.args =
+                    shell_words::split(&String::from_utf8_lossy(data)).map_err(|err| {
+                        let (line_number, line) = lines().next().unwrap_or((line_number + 1, b""));
+                        error(line_number, line, format!("Invalid arguments: {err}"))
+                    })?
+            }
+            Section::Env => {
+                for (line_number, line) in lines() {
+                    let variable = String::from_utf8_lossy(line);
+                    let (key, value) = parse_env_var(variable.trim()).map_err(|err| {
+                        error(line_number, line, format!("Invalid variable: {err}"))
+                    })?;
+                    test.run_args.env.insert(key, value);
+                }
+            }
+            Section::Limits => {
+                for (line_number, line) in lines() {
+                    let limit = String::from_utf8_lossy(line);
+                    let invalid =
+                        |err: String| error(line_number, line, format!("Invalid limit: {err}"));
+
+                    match limit
+                        .trim()
+                        .split_once(':')
+                        .map(|(kind, value)| (kind.trim(), value))
+                    {
+                        Some(("time", value)) => {
+                            test.run_args.time_limit = Some(parse_duration(value).map_err(invalid)?)
+                        }
+                        Some(("memory", value)) => {
+                            test.run_args.memory_limit = Some(parse_size(value).map_err(invalid)?)
+                        }
+                        _ => {
+                            return Err(invalid(format!(
+                                "\"{}\" isn't \"time: <duration>\" or \"memory: <size>\".",
+                                limit.trim()
+                            )))
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Iterator for SectionFileIterator {
+    type Item = Result<SimpleTest, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let case = self.read_case().transpose();
+        // Nothing after a malformed case can be told apart reliably.
+        self.done = !matches!(case, Some(Ok(_)));
+        case
+    }
+}
+
+/// Lines starting with `===` after some backslashes, which would otherwise
+/// read as headers, are written with one more backslash.
+fn is_escaped_header(line: &[u8]) -> bool {
+    let backslashes = line.iter().take_while(|&&byte| byte == b'\\').count();
+    line[backslashes..].starts_with(SECTION_PREFIX)
+}
+
+fn unescape(line: &[u8]) -> &[u8] {
+    match line.starts_with(b"\\") && is_escaped_header(line) {
+        true => &line[1..],
+        false => line,
+    }
+}
+
+/// Copies `data` line by line, so inputs left in their test file are never
+/// loaded whole.
+fn write_data(writer: &mut impl Write, data: &TestInput) -> io::Result<()> {
+    let mut reader = BufReader::new(data.reader()?);
+    let mut line = Vec::new();
+
+    loop {
+        line.clear();
+        reader.read_until(b'\n', &mut line)?;
+        if is_escaped_header(&line) {
+            writer.write_all(b"\\")?;
+        }
+        writer.write_all(&line)?;
+
+        if !line.ends_with(b"\n") {
+            break;
+        }
+    }
+
+    writer.write_all(b"\n")
+}
+
+fn format_duration(duration: Duration) -> String {
+    match duration.subsec_nanos() {
+        0 => format!("{}s", duration.as_secs()),
+        _ => format!("{}ms", duration.as_secs_f64() * 1e3),
+    }
+}
+
+fn format_size(size: usize) -> String {
+    [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)]
+        .into_iter()
+        .find(|&(_, unit)| size >= unit && size.is_multiple_of(unit))
+        .map_or_else(
+            || size.to_string(),
+            |(suffix, unit)| format!("{}{suffix}", size / unit),
+        )
+}
+
+/// Reads the lines before the first case of a section file, its header and
+/// comments, so they can be kept when it is rewritten.
+pub fn read_preamble(path: &Path) -> io::Result<Vec<u8>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut preamble = Vec::new();
+    let mut line = Vec::new();
+
+    while reader.read_until(b'\n', &mut line)? > 0 && !line.starts_with(SECTION_PREFIX) {
+        preamble.append(&mut line);
+    }
+
+    Ok(preamble)
+}
+
+/// Writes `tests` as a section file after `preamble`, the header when none
+/// is given. The file reads back into the same tests.
+pub fn write_section_file(
+    writer: &mut impl Write,
+    preamble: Option<&[u8]>,
+    tests: &[SimpleTest],
+) -> io::Result<()> {
+    match preamble {
+        Some(preamble) => writer.write_all(preamble)?,
+        None => writeln!(writer, "{SECTION_FILE_HEADER}")?,
+    }
+
+    for test in tests {
+        write!(writer, "=== input")?;
+        if let Some(name) = &test.labels.name {
+            write!(writer, " {name}")?;
+        }
+        for tag in &test.labels.tags {
+            write!(writer, " @{tag}")?;
+        }
+        writeln!(writer)?;
+        write_data(writer, &test.input)?;
+
+        if let Some(expected_output) = &test.expected_output {
+            writeln!(writer, "=== output")?;
+            write_data(writer, expected_output)?;
+        }
+
+        let RunArgs {
+            args,
+            env,
+            time_limit,
+            memory_limit,
+        } = &test.run_args;
+
+        if !args.is_empty() {
+            writeln!(writer, "=== args")?;
+            writeln!(writer, "{}", shell_words::join(args))?;
+        }
+        if !env.is_empty() {
+            writeln!(writer, "=== env")?;
+            for (key, value) in env {
+                writeln!(writer, "{key}={value}")?;
+            }
+        }
+        if time_limit.is_some() || memory_limit.is_some() {
+            writeln!(writer, "=== limits")?;
+            if let Some(time_limit) = time_limit {
+                writeln!(writer, "time: {}", format_duration(*time_limit))?;
+            }
+            if let Some(memory_limit) = memory_limit {
+                writeln!(writer, "memory: {}", format_size(*memory_limit))?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_file::read_test_file;
+    use std::fs;
+    use std::path::PathBuf;
+
+    /// A file under the system temp directory, removed on drop.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &[u8]) -> Self {
+            let path = std::env::temp_dir().join(format!("erunner-{}-{name}", std::process::id()));
+            fs::write(&path, contents).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    type Case = (Vec<u8>, Option<Vec<u8>>, RunArgs, TestLabels);
+
+    fn read(data: &TestInput) -> Vec<u8> {
+        data.head(usize::MAX).unwrap()
+    }
+
+    fn cases(path: &Path) -> Vec<Case> {
+        read_test_file(path)
+            .unwrap()
+            .map(|test| {
+                let test = test.unwrap();
+                (
+                    read(&test.input),
+                    test.expected_output.as_ref().map(read),
+                    test.run_args,
+                    test.labels,
+                )
+            })
+            .collect()
+    }
+
+    fn labels(name: Option<&str>, tags: &[&str]) -> TestLabels {
+        TestLabels {
+            name: name.map(str::to_string),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn converted_brace_file_reads_back_the_same() {
+        let brace_file = TempFile::new(
+            "convert.txt",
+            b"#name: small\n#tag: edge\n{ 1 2 } -> { 3 }\n\
+              #args: --mode \"two words\"\n{\n  10 20\n  === not a header\n} -> { 30 }\n\
+              #env: ONLINE_JUDGE=1\n#name: big\n{ 5 5 } -> { 10 }\n",
+        );
+        let expected = cases(&brace_file.0);
+
+        let mut contents = Vec::new();
+        let tests = read_test_file(&brace_file.0)
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        write_section_file(&mut contents, None, &tests).unwrap();
+        let section_file = TempFile::new("convert.tests", &contents);
+
+        assert!(contents.starts_with(SECTION_FILE_HEADER.as_bytes()));
+        assert!(contents
+            .split(|&byte| byte == b'\n')
+            .any(|line| line == b"\\=== not a header"));
+
+        let converted = cases(&section_file.0);
+        assert_eq!(converted, expected);
+        assert_eq!(
+            converted
+                .iter()
+                .map(|(.., labels)| labels.name.as_deref())
+                .collect::<Vec<_>>(),
+            [Some("small"), None, Some("big")]
+        );
+        assert_eq!(converted[1].0, b"10 20\n=== not a header");
+        assert_eq!(converted[2].2.env["ONLINE_JUDGE"], "1");
+    }
+
+    #[test]
+    fn names_and_tags_label_inputs() {
+        let file = TempFile::new(
+            "labels.tests",
+            b"=== input max-n @edge @slow\n1\n=== output\n2\n=== input\n3\n=== input @edge\n4\n",
+        );
+
+        assert_eq!(
+            cases(&file.0)
+                .into_iter()
+                .map(|(.., labels)| labels)
+                .collect::<Vec<_>>(),
+            [
+                labels(Some("max-n"), &["edge", "slow"]),
+                labels(None, &[]),
+                labels(None, &["edge"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_sections() {
+        let file = TempFile::new(
+            "empty.tests",
+            b"=== input\n=== output\n=== args\n=== env\n=== limits\n\
+              === input blank\n\n=== output\n\n\n=== input last",
+        );
+
+        assert_eq!(
+            cases(&file.0),
+            [
+                (
+                    Vec::new(),
+                    Some(Vec::new()),
+                    RunArgs::default(),
+                    labels(None, &[])
+                ),
+                (
+                    Vec::new(),
+                    Some(b"\n".to_vec()),
+                    RunArgs::default(),
+                    labels(Some("blank"), &[])
+                ),
+                (
+                    Vec::new(),
+                    None,
+                    RunArgs::default(),
+                    labels(Some("last"), &[])
+                ),
+            ]
+        );
+    }
+}
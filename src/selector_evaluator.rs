@@ -1,137 +1,1015 @@
 use std::fmt;
-use std::ops::RangeInclusive;
 
-#[derive(Debug)]
-pub enum RangeEvaluationError {
-    InvalidRangeFormat(String),
+use crate::cache_file::TestLabels;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectorErrorKind {
+    UnexpectedCharacter(char),
+    UnexpectedToken(String),
+    UnexpectedEnd,
     InvalidNumber(String),
+    ZeroIndex,
+    InvalidStep,
+    EmptyRange,
+    ReversedRange,
+    MixedWildcard,
+    MissingTest { index: usize, test_count: usize },
 }
 
-impl fmt::Display for RangeEvaluationError {
+impl fmt::Display for SelectorErrorKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            RangeEvaluationError::InvalidRangeFormat(part) => {
-                write!(f, "Invalid range format: {}", part)
+            SelectorErrorKind::UnexpectedCharacter(chr) => {
+                write!(f, "Unexpected character '{chr}'")
             }
-            RangeEvaluationError::InvalidNumber(part) => {
-                write!(f, "Invalid number: {}", part)
+            SelectorErrorKind::UnexpectedToken(token) => write!(f, "Unexpected \"{token}\""),
+            SelectorErrorKind::UnexpectedEnd => write!(f, "Unexpected end of selector"),
+            SelectorErrorKind::InvalidNumber(number) => write!(f, "Invalid number {number}"),
+            SelectorErrorKind::ZeroIndex => write!(f, "Tests are numbered from 1"),
+            SelectorErrorKind::InvalidStep => write!(
+                f,
+                "A step needs a range within one test, or one without sub-tests"
+            ),
+            SelectorErrorKind::EmptyRange => write!(f, "A range needs at least one end"),
+            SelectorErrorKind::ReversedRange => write!(f, "Range starts after it ends"),
+            SelectorErrorKind::MixedWildcard => {
+                write!(f, "'*' must be used on both ends of a range")
             }
+            SelectorErrorKind::MissingTest { index, test_count } => write!(
+                f,
+                "Test {index} doesn't exist, it must be in the range of 1 to {test_count}"
+            ),
         }
     }
 }
 
+/// A selector error, pointing at the column it was found at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RangeEvaluationError {
+    pub expression: String,
+    /// 1-based, in characters.
+    pub column: usize,
+    pub kind: SelectorErrorKind,
+}
+
+impl fmt::Display for RangeEvaluationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at column {}.\n  {}\n  {}^",
+            self.kind,
+            self.column,
+            self.expression,
+            " ".repeat(self.column - 1)
+        )
+    }
+}
+
+impl std::error::Error for RangeEvaluationError {}
+
+/// Position of a test or sub-test: a number, `$` for the last one, or `*`
+/// for any.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Index {
+    At(usize),
+    Last,
+    Any,
+}
+
+impl fmt::Display for Index {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Index::At(index) => write!(f, "{index}"),
+            Index::Last => write!(f, "$"),
+            Index::Any => write!(f, "*"),
+        }
+    }
+}
+
+/// `main.sub`, where an omitted sub-test is `Index::Any`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub main: Index,
+    pub sub: Index,
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.sub {
+            Index::Any => write!(f, "{}", self.main),
+            sub => write!(f, "{}.{}", self.main, sub),
+        }
+    }
+}
+
+/// Every `n`th test, or every `n`th sub-test within one test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step {
+    Tests(usize),
+    SubTests(usize),
+}
+
+/// Tests between `start` and `end` in `(test, sub-test)` order. Open ends
+/// reach the first or last test, and a single position is both ends.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TestsRange {
+    pub start: Option<Position>,
+    pub end: Option<Position>,
+    pub step: Option<Step>,
+    pub column: usize,
+}
+
+impl fmt::Display for TestsRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.start, self.end) {
+            (Some(start), Some(end)) if start == end => write!(f, "{start}")?,
+            (start, end) => {
+                if let Some(start) = start {
+                    write!(f, "{start}")?;
+                }
+                write!(f, "-")?;
+                if let Some(end) = end {
+                    write!(f, "{end}")?;
+                }
+            }
+        }
+
+        match self.step {
+            Some(Step::Tests(step) | Step::SubTests(step)) => write!(f, ":{step}"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Where a test or sub-test is, along with what `$` stands for.
+#[derive(Debug, Clone, Copy)]
+pub struct TestPosition {
     pub main_test: usize,
-    pub sub_tests: Option<RangeInclusive<usize>>,
+    pub sub_test: Option<usize>,
+    /// Only needed for `$` sub-tests, see `Selection::needs_sub_test_count`.
+    pub sub_test_count: Option<usize>,
+}
+
+impl TestPosition {
+    pub fn test(main_test: usize) -> Self {
+        Self {
+            main_test,
+            sub_test: None,
+            sub_test_count: None,
+        }
+    }
+
+    pub fn sub_test(main_test: usize, sub_test: usize, sub_test_count: Option<usize>) -> Self {
+        Self {
+            main_test,
+            sub_test: Some(sub_test),
+            sub_test_count,
+        }
+    }
+}
+
+impl TestsRange {
+    fn resolve_main(index: Index, position: &TestPosition, test_count: usize) -> usize {
+        match index {
+            Index::At(index) => index,
+            Index::Last => test_count,
+            Index::Any => position.main_test,
+        }
+    }
+
+    fn resolve_sub(index: Index, position: &TestPosition, default: usize) -> usize {
+        match index {
+            Index::At(index) => index,
+            // An unknown count can't match any sub-test.
+            Index::Last => position.sub_test_count.unwrap_or(0),
+            Index::Any => default,
+        }
+    }
+
+    fn contains(&self, position: &TestPosition, test_count: usize) -> bool {
+        let start_main = self.start.map_or(1, |start| {
+            Self::resolve_main(start.main, position, test_count)
+        });
+        let end_main = self.end.map_or(usize::MAX, |end| {
+            Self::resolve_main(end.main, position, test_count)
+        });
+
+        if let Some(Step::Tests(step)) = self.step {
+            if position.main_test < start_main
+                || !(position.main_test - start_main).is_multiple_of(step)
+            {
+                return false;
+            }
+        }
+
+        let Some(sub_test) = position.sub_test else {
+            return (start_main..=end_main).contains(&position.main_test);
+        };
+
+        let start_sub = self
+            .start
+            .map_or(1, |start| Self::resolve_sub(start.sub, position, 1));
+        let end_sub = self.end.map_or(usize::MAX, |end| {
+            Self::resolve_sub(end.sub, position, usize::MAX)
+        });
+
+        if let Some(Step::SubTests(step)) = self.step {
+            if sub_test < start_sub || !(sub_test - start_sub).is_multiple_of(step) {
+                return false;
+            }
+        }
+
+        ((start_main, start_sub)..=(end_main, end_sub)).contains(&(position.main_test, sub_test))
+    }
+
+    /// Whether the range covers whole tests, never only some of their sub-tests.
+    fn covers_whole_tests(&self) -> bool {
+        [self.start, self.end]
+            .into_iter()
+            .flatten()
+            .all(|position| position.sub == Index::Any)
+            && !matches!(self.step, Some(Step::SubTests(_)))
+    }
+
+    /// Whether the range only selects sub-tests of the test at `main_test`.
+    pub fn is_within(&self, main_test: usize, test_count: usize) -> bool {
+        let position = TestPosition::test(main_test);
+
+        match (self.start, self.end) {
+            (Some(start), Some(end)) => {
+                Self::resolve_main(start.main, &position, test_count) == main_test
+                    && Self::resolve_main(end.main, &position, test_count) == main_test
+                    && !self.covers_whole_tests()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// One comma separated part of a selector.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selector {
+    Range(TestsRange),
+    Name(String),
+    Tag(String),
+}
+
+impl Selector {
+    /// `labels` holds the labels of the test followed by those of the sub-test.
+    fn matches(&self, position: &TestPosition, test_count: usize, labels: &[&TestLabels]) -> bool {
+        match self {
+            Selector::Range(range) => range.contains(position, test_count),
+            Selector::Name(name) => labels
+                .iter()
+                .any(|labels| labels.name.as_ref() == Some(name)),
+            Selector::Tag(tag) => labels.iter().any(|labels| labels.tags.contains(tag)),
+        }
+    }
+}
+
+/// Tests matching any of `include` (every test when empty) and none of `exclude`.
+#[derive(Debug)]
+pub struct Selection {
+    pub include: Vec<Selector>,
+    pub exclude: Vec<Selector>,
+    pub test_count: usize,
+}
+
+impl Selection {
+    /// Whether some sub-tests of the test at `main_test` may be selected, so
+    /// its test file has to be read.
+    pub fn may_select(&self, main_test: usize, labels: &TestLabels) -> bool {
+        let position = TestPosition::test(main_test);
+
+        let included = self.include.is_empty()
+            || self.include.iter().any(|selector| match selector {
+                Selector::Range(_) => selector.matches(&position, self.test_count, &[labels]),
+                // Sub-tests can carry names and tags of their own.
+                Selector::Name(_) | Selector::Tag(_) => true,
+            });
+        let excluded = self.exclude.iter().any(|selector| match selector {
+            Selector::Range(range) => {
+                range.covers_whole_tests() && range.contains(&position, self.test_count)
+            }
+            Selector::Name(_) | Selector::Tag(_) => {
+                selector.matches(&position, self.test_count, &[labels])
+            }
+        });
+
+        included && !excluded
+    }
+
+    pub fn selects(&self, position: TestPosition, labels: &[&TestLabels]) -> bool {
+        let matches = |selector: &Selector| selector.matches(&position, self.test_count, labels);
+
+        (self.include.is_empty() || self.include.iter().any(matches))
+            && !self.exclude.iter().any(matches)
+    }
+
+    /// Whether `$` is used for a sub-test, which can only be resolved once
+    /// the sub-tests of a file are counted.
+    pub fn needs_sub_test_count(&self) -> bool {
+        self.ranges().any(|range| {
+            [range.start, range.end]
+                .into_iter()
+                .flatten()
+                .any(|position| position.sub == Index::Last)
+        })
+    }
+
+    pub fn ranges(&self) -> impl Iterator<Item = &TestsRange> {
+        self.include
+            .iter()
+            .chain(&self.exclude)
+            .filter_map(|selector| match selector {
+                Selector::Range(range) => Some(range),
+                _ => None,
+            })
+    }
 }
 
-fn process_expression(expression: &str) -> Result<Vec<TestsRange>, RangeEvaluationError> {
-    if expression.contains('-') {
-        // Process main test case or sub-test range (e.g., "1.1-1.5" or "2-4")
-        let parts: Vec<_> = expression.split('-').collect();
+/// Checks that a test name or tag can be told apart from a position in selectors.
+pub fn parse_label(label: &str) -> Result<String, String> {
+    let mut chars = label.chars();
+
+    match chars.next() {
+        Some(first) if is_label_start(first) && chars.all(is_label_char) => Ok(label.to_string()),
+        _ => Err(format!(
+            "\"{label}\" must start with a letter and only contain letters, digits, '-' and '_'."
+        )),
+    }
+}
+
+fn is_label_start(chr: char) -> bool {
+    chr.is_alphabetic()
+}
+
+fn is_label_char(chr: char) -> bool {
+    chr.is_alphanumeric() || chr == '-' || chr == '_'
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Number(usize),
+    Label(String),
+    Last,
+    Any,
+    Dot,
+    Dash,
+    Colon,
+    Comma,
+    Bang,
+    At,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(number) => write!(f, "{number}"),
+            Token::Label(label) => write!(f, "{label}"),
+            Token::Last => write!(f, "$"),
+            Token::Any => write!(f, "*"),
+            Token::Dot => write!(f, "."),
+            Token::Dash => write!(f, "-"),
+            Token::Colon => write!(f, ":"),
+            Token::Comma => write!(f, ","),
+            Token::Bang => write!(f, "!"),
+            Token::At => write!(f, "@"),
+        }
+    }
+}
+
+/// Splits a selector into tokens along with their 1-based columns.
+fn tokenize(expression: &str) -> Result<Vec<(Token, usize)>, (SelectorErrorKind, usize)> {
+    let chars: Vec<char> = expression.chars().collect();
+    let mut tokens = Vec::new();
+    let mut index = 0;
+
+    while index < chars.len() {
+        let column = index + 1;
+        let chr = chars[index];
+
+        let token = match chr {
+            _ if chr.is_whitespace() => {
+                index += 1;
+                continue;
+            }
+            '0'..='9' => {
+                let end = (index..chars.len())
+                    .find(|&end| !chars[end].is_ascii_digit())
+                    .unwrap_or(chars.len());
+                let digits: String = chars[index..end].iter().collect();
+                index = end;
+
+                let number = digits
+                    .parse::<usize>()
+                    .map_err(|_| (SelectorErrorKind::InvalidNumber(digits), column))?;
+                tokens.push((Token::Number(number), column));
+                continue;
+            }
+            _ if is_label_start(chr) => {
+                let end = (index..chars.len())
+                    .find(|&end| !is_label_char(chars[end]))
+                    .unwrap_or(chars.len());
+                let label = chars[index..end].iter().collect();
+                index = end;
+
+                tokens.push((Token::Label(label), column));
+                continue;
+            }
+            '$' => Token::Last,
+            '*' => Token::Any,
+            '.' => Token::Dot,
+            '-' => Token::Dash,
+            ':' => Token::Colon,
+            ',' => Token::Comma,
+            '!' => Token::Bang,
+            '@' => Token::At,
+            _ => return Err((SelectorErrorKind::UnexpectedCharacter(chr), column)),
+        };
+
+        tokens.push((token, column));
+        index += 1;
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over the tokens of one selector.
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    end_column: usize,
+}
+
+type ParseResult<T> = Result<T, (SelectorErrorKind, usize)>;
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn column(&self) -> usize {
+        self.tokens
+            .get(self.position)
+            .map_or(self.end_column, |&(_, column)| column)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .map(|(token, _)| token.clone());
+        self.position += 1;
+        token
+    }
+
+    fn unexpected(&self) -> (SelectorErrorKind, usize) {
+        let kind = match self.peek() {
+            Some(token) => SelectorErrorKind::UnexpectedToken(token.to_string()),
+            None => SelectorErrorKind::UnexpectedEnd,
+        };
+        (kind, self.column())
+    }
+
+    fn selection(&mut self) -> ParseResult<(Vec<Selector>, Vec<Selector>)> {
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
 
-        if parts.len() != 2 {
-            return Err(RangeEvaluationError::InvalidRangeFormat(
-                "Range must have exactly one '-'".to_string(),
-            ));
+        loop {
+            let excluded = self.peek() == Some(&Token::Bang);
+            if excluded {
+                self.next();
+            }
+
+            let selector = self.selector()?;
+            match excluded {
+                true => exclude.push(selector),
+                false => include.push(selector),
+            }
+
+            match self.peek() {
+                Some(Token::Comma) => {
+                    self.next();
+                }
+                None => return Ok((include, exclude)),
+                Some(_) => return Err(self.unexpected()),
+            }
         }
+    }
 
-        // Split both parts into main and sub-tests, if they contain '.'
-        let left_parts: Vec<_> = parts[0].split('.').collect();
-        let right_parts: Vec<_> = parts[1].split('.').collect();
+    fn selector(&mut self) -> ParseResult<Selector> {
+        match self.peek() {
+            Some(Token::At) => {
+                self.next();
+                match self.peek() {
+                    Some(Token::Label(_)) => match self.next() {
+                        Some(Token::Label(tag)) => Ok(Selector::Tag(tag)),
+                        _ => unreachable!("Because the token was just peeked."),
+                    },
+                    _ => Err(self.unexpected()),
+                }
+            }
+            Some(Token::Label(_)) => match self.next() {
+                Some(Token::Label(name)) => Ok(Selector::Name(name)),
+                _ => unreachable!("Because the token was just peeked."),
+            },
+            _ => self.range().map(Selector::Range),
+        }
+    }
 
-        let main_start = left_parts[0]
-            .parse::<usize>()
-            .map_err(|_| RangeEvaluationError::InvalidNumber(left_parts[0].to_string()))?;
-        let main_end = right_parts[0]
-            .parse::<usize>()
-            .map_err(|_| RangeEvaluationError::InvalidNumber(right_parts[0].to_string()))?;
+    fn range(&mut self) -> ParseResult<TestsRange> {
+        let column = self.column();
+        let start = self.position_if_any()?;
 
-        let sub_start = if left_parts.len() == 2 {
-            left_parts[1]
-                .parse::<usize>()
-                .map_err(|_| RangeEvaluationError::InvalidNumber(left_parts[1].to_string()))?
+        let end = if self.peek() == Some(&Token::Dash) {
+            self.next();
+            self.position_if_any()?
         } else {
-            1 // Default to sub-test 1 if not specified
+            match start {
+                Some(_) => start,
+                None => return Err(self.unexpected()),
+            }
         };
 
-        let sub_end = if right_parts.len() == 2 {
-            right_parts[1]
-                .parse::<usize>()
-                .map_err(|_| RangeEvaluationError::InvalidNumber(right_parts[1].to_string()))?
+        if start.is_none() && end.is_none() {
+            return Err((SelectorErrorKind::EmptyRange, column));
+        }
+
+        let step = if self.peek() == Some(&Token::Colon) {
+            self.next();
+            let step_column = self.column();
+
+            match self.next() {
+                Some(Token::Number(step)) if step > 0 => Some((step, step_column)),
+                Some(Token::Number(_)) => {
+                    return Err((SelectorErrorKind::InvalidStep, step_column))
+                }
+                _ => {
+                    self.position -= 1;
+                    return Err(self.unexpected());
+                }
+            }
         } else {
-            usize::MAX // Default to all sub-tests if not specified
+            None
         };
 
-        let mut results = Vec::new();
+        let mut range = TestsRange {
+            start,
+            end,
+            step: None,
+            column,
+        };
+        validate_range(&range)?;
 
-        if main_start == main_end {
-            // If the range is within the same main test case (e.g., "1.1-1.5")
-            results.push(TestsRange {
-                main_test: main_start,
-                sub_tests: Some(sub_start..=sub_end),
-            });
+        if let Some((step, step_column)) = step {
+            range.step = Some(
+                step_level(&range, step).ok_or((SelectorErrorKind::InvalidStep, step_column))?,
+            );
+        }
+
+        Ok(range)
+    }
+
+    fn position_if_any(&mut self) -> ParseResult<Option<Position>> {
+        let Some(main) = self.index_if_any()? else {
+            return Ok(None);
+        };
+
+        let sub = if self.peek() == Some(&Token::Dot) {
+            self.next();
+            match self.index_if_any()? {
+                Some(sub) => sub,
+                None => return Err(self.unexpected()),
+            }
         } else {
-            // Handle range across different main test cases (e.g., "3.2-4.2")
-            results.push(TestsRange {
-                main_test: main_start,
-                sub_tests: Some(sub_start..=usize::MAX),
-            }); // From sub_start to all remaining in main_start
-            for main_test in (main_start + 1)..main_end {
-                results.push(TestsRange {
-                    main_test,
-                    sub_tests: None,
-                }); // Full sub-tests for main tests in between
-            }
-            results.push(TestsRange {
-                main_test: main_end,
-                sub_tests: Some(1..=sub_end),
-            }); // From start in the last main test case to sub_end
-        }
-
-        Ok(results)
-    } else if expression.contains('.') {
-        // Handle single ref-test (e.g., "1.2")
-        let parts: Vec<_> = expression.split('.').collect();
-
-        if parts.len() != 2 {
-            return Err(RangeEvaluationError::InvalidRangeFormat(
-                "Ref-test format must have exactly one '.'".to_string(),
-            ));
-        }
-
-        let main_test = parts[0]
-            .parse::<usize>()
-            .map_err(|_| RangeEvaluationError::InvalidNumber(parts[0].to_string()))?;
-        let sub_test = parts[1]
-            .parse::<usize>()
-            .map_err(|_| RangeEvaluationError::InvalidNumber(parts[1].to_string()))?;
-
-        Ok(vec![TestsRange {
-            main_test,
-            sub_tests: Some(sub_test..=sub_test),
-        }])
-    } else {
-        // Handle single main test case (e.g., "2")
-        let single_test = expression
-            .parse::<usize>()
-            .map_err(|_| RangeEvaluationError::InvalidNumber(expression.to_string()))?;
-        Ok(vec![TestsRange {
-            main_test: single_test,
-            sub_tests: None,
-        }]) // Include all sub-tests for the main test case
+            Index::Any
+        };
+
+        Ok(Some(Position { main, sub }))
+    }
+
+    fn index_if_any(&mut self) -> ParseResult<Option<Index>> {
+        let column = self.column();
+
+        let index = match self.peek() {
+            Some(Token::Number(0)) => return Err((SelectorErrorKind::ZeroIndex, column)),
+            Some(&Token::Number(number)) => Index::At(number),
+            Some(Token::Last) => Index::Last,
+            Some(Token::Any) => Index::Any,
+            _ => return Ok(None),
+        };
+
+        self.next();
+        Ok(Some(index))
+    }
+}
+
+/// Rejects ranges that can never match, as far as it's known before `$` is resolved.
+fn validate_range(range: &TestsRange) -> ParseResult<()> {
+    let (Some(start), Some(end)) = (range.start, range.end) else {
+        if [range.start, range.end]
+            .into_iter()
+            .flatten()
+            .any(|position| position.main == Index::Any)
+        {
+            return Err((SelectorErrorKind::MixedWildcard, range.column));
+        }
+        return Ok(());
+    };
+
+    if (start.main == Index::Any) != (end.main == Index::Any) {
+        return Err((SelectorErrorKind::MixedWildcard, range.column));
+    }
+
+    let same_test = match (start.main, end.main) {
+        (Index::At(start), Index::At(end)) if start > end => {
+            return Err((SelectorErrorKind::ReversedRange, range.column))
+        }
+        (start, end) => start == end,
+    };
+
+    if same_test {
+        if let (Index::At(start), Index::At(end)) = (start.sub, end.sub) {
+            if start > end {
+                return Err((SelectorErrorKind::ReversedRange, range.column));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// What a step of `range` counts, if it can be told.
+fn step_level(range: &TestsRange, step: usize) -> Option<Step> {
+    let positions = [range.start, range.end];
+
+    if positions
+        .into_iter()
+        .flatten()
+        .all(|position| position.sub == Index::Any)
+    {
+        return Some(Step::Tests(step));
+    }
+
+    match (range.start, range.end) {
+        (Some(start), Some(end)) if start.main == end.main => Some(Step::SubTests(step)),
+        _ => None,
+    }
+}
+
+/// Parses a selector such as `1,3.2-4,2-:2,*.1,$,max-n,@edge,!@slow` for a
+/// file having `test_count` tests.
+pub fn evaluate(expression: &str, test_count: usize) -> Result<Selection, RangeEvaluationError> {
+    let error = |(kind, column)| RangeEvaluationError {
+        expression: expression.to_string(),
+        column,
+        kind,
+    };
+
+    let tokens = tokenize(expression).map_err(error)?;
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        end_column: expression.chars().count() + 1,
+    };
+    let (include, exclude) = parser.selection().map_err(error)?;
+
+    let selection = Selection {
+        include,
+        exclude,
+        test_count,
+    };
+
+    for range in selection.ranges() {
+        let resolve = |index| match index {
+            Index::At(index) => Some(index),
+            Index::Last => Some(test_count),
+            Index::Any => None,
+        };
+        let mains: Vec<usize> = [range.start, range.end]
+            .into_iter()
+            .flatten()
+            .filter_map(|position| resolve(position.main))
+            .collect();
+
+        if let Some(&index) = mains
+            .iter()
+            .find(|&&index| index == 0 || index > test_count)
+        {
+            return Err(error((
+                SelectorErrorKind::MissingTest { index, test_count },
+                range.column,
+            )));
+        }
+
+        if let [start, end] = mains[..] {
+            if start > end {
+                return Err(error((SelectorErrorKind::ReversedRange, range.column)));
+            }
+        }
     }
+
+    Ok(selection)
 }
 
-pub fn evaluate(expressions: &str) -> Result<Vec<TestsRange>, RangeEvaluationError> {
-    let mut result_expressions: Vec<TestsRange> = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    for expression in expressions.split(',') {
-        match process_expression(expression.trim()) {
-            Ok(mut result) => result_expressions.append(&mut result), // Append the flattened result
-            Err(err) => return Err(err),
+    fn labels(name: Option<&str>, tags: &[&str]) -> TestLabels {
+        TestLabels {
+            name: name.map(str::to_string),
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
         }
     }
 
-    Ok(result_expressions)
+    fn error_of(expression: &str, test_count: usize) -> (SelectorErrorKind, usize) {
+        let error = evaluate(expression, test_count).unwrap_err();
+        (error.kind, error.column)
+    }
+
+    /// Tests of a selection among `test_count` tests without sub-tests.
+    fn selected_tests(expression: &str, test_count: usize) -> Vec<usize> {
+        let selection = evaluate(expression, test_count).unwrap();
+        let none = TestLabels::default();
+
+        (1..=test_count)
+            .filter(|&main_test| selection.selects(TestPosition::test(main_test), &[&none]))
+            .collect()
+    }
+
+    /// Sub-tests of test `main_test` holding `sub_test_count` of them.
+    fn selected_sub_tests(
+        expression: &str,
+        test_count: usize,
+        main_test: usize,
+        sub_test_count: usize,
+    ) -> Vec<usize> {
+        let selection = evaluate(expression, test_count).unwrap();
+        let none = TestLabels::default();
+
+        (1..=sub_test_count)
+            .filter(|&sub_test| {
+                selection.selects(
+                    TestPosition::sub_test(main_test, sub_test, Some(sub_test_count)),
+                    &[&none, &none],
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn single_tests() {
+        assert_eq!(selected_tests("2", 5), vec![2]);
+        assert_eq!(selected_tests("1, 3,5", 5), vec![1, 3, 5]);
+        assert_eq!(selected_tests("$", 5), vec![5]);
+        assert_eq!(selected_tests("*", 3), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn closed_ranges() {
+        assert_eq!(selected_tests("2-4", 5), vec![2, 3, 4]);
+        assert_eq!(selected_tests("3-3", 5), vec![3]);
+        assert_eq!(selected_tests("2-$", 4), vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn open_ranges() {
+        assert_eq!(selected_tests("3-", 5), vec![3, 4, 5]);
+        assert_eq!(selected_tests("-2", 5), vec![1, 2]);
+        assert_eq!(selected_sub_tests("2.3-", 3, 2, 5), vec![3, 4, 5]);
+        assert_eq!(selected_sub_tests("2.3-", 3, 3, 2), vec![1, 2]);
+        assert_eq!(selected_sub_tests("-2.2", 3, 2, 5), vec![1, 2]);
+        assert!(selected_sub_tests("-2.2", 3, 3, 5).is_empty());
+    }
+
+    #[test]
+    fn steps() {
+        assert_eq!(selected_tests("1-6:2", 6), vec![1, 3, 5]);
+        assert_eq!(selected_tests("2-:3", 9), vec![2, 5, 8]);
+        assert_eq!(selected_sub_tests("1.2-1.9:3", 1, 1, 10), vec![2, 5, 8]);
+        assert_eq!(selected_sub_tests("1.2-1.$:4", 1, 1, 10), vec![2, 6, 10]);
+    }
+
+    #[test]
+    fn sub_tests() {
+        assert_eq!(selected_sub_tests("1.2", 2, 1, 4), vec![2]);
+        assert_eq!(selected_sub_tests("1.2-1.3", 2, 1, 4), vec![2, 3]);
+        assert_eq!(selected_sub_tests("1", 2, 1, 3), vec![1, 2, 3]);
+        assert_eq!(selected_sub_tests("1.*", 2, 1, 3), vec![1, 2, 3]);
+        assert_eq!(selected_sub_tests("1.$", 2, 1, 4), vec![4]);
+        assert!(selected_sub_tests("1.2", 2, 2, 4).is_empty());
+    }
+
+    #[test]
+    fn ranges_across_tests() {
+        assert_eq!(selected_sub_tests("1.3-3.2", 3, 1, 4), vec![3, 4]);
+        assert_eq!(selected_sub_tests("1.3-3.2", 3, 2, 4), vec![1, 2, 3, 4]);
+        assert_eq!(selected_sub_tests("1.3-3.2", 3, 3, 4), vec![1, 2]);
+        // Whole tests without sub-tests of a range only partly covering them.
+        assert_eq!(selected_tests("1.3-3.2", 4), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn wildcard_tests() {
+        assert_eq!(selected_sub_tests("*.1", 3, 2, 4), vec![1]);
+        assert_eq!(selected_sub_tests("*.$", 3, 3, 4), vec![4]);
+        assert_eq!(selected_sub_tests("*.2-*.3", 3, 1, 4), vec![2, 3]);
+    }
+
+    #[test]
+    fn names_tags_and_negation() {
+        let selection = evaluate("max-n,@edge,!@slow,!2", 3).unwrap();
+        let max_n = labels(Some("max-n"), &[]);
+        let edge = labels(None, &["edge"]);
+        let slow_edge = labels(None, &["edge", "slow"]);
+        let none = TestLabels::default();
+
+        assert!(selection.selects(TestPosition::test(1), &[&max_n]));
+        assert!(selection.selects(TestPosition::test(3), &[&edge]));
+        assert!(!selection.selects(TestPosition::test(3), &[&slow_edge]));
+        assert!(!selection.selects(TestPosition::test(2), &[&edge]));
+        assert!(!selection.selects(TestPosition::test(1), &[&none]));
+        assert!(selection.selects(TestPosition::sub_test(1, 4, None), &[&none, &edge]));
+    }
+
+    #[test]
+    fn exclusions_alone_start_from_every_test() {
+        assert_eq!(selected_tests("!2", 3), vec![1, 3]);
+        assert_eq!(selected_tests("!2-", 3), vec![1]);
+        assert_eq!(selected_sub_tests("!*.1", 1, 1, 3), vec![2, 3]);
+    }
+
+    #[test]
+    fn files_are_skipped_only_when_excluded_as_a_whole() {
+        let edge = labels(None, &["edge"]);
+        let none = TestLabels::default();
+
+        let selection = evaluate("@edge", 3).unwrap();
+        assert!(selection.may_select(2, &none));
+
+        let selection = evaluate("!@edge", 3).unwrap();
+        assert!(!selection.may_select(2, &edge));
+        assert!(selection.may_select(2, &none));
+
+        let selection = evaluate("!2.1", 3).unwrap();
+        assert!(selection.may_select(2, &none));
+
+        let selection = evaluate("!2", 3).unwrap();
+        assert!(!selection.may_select(2, &none));
+
+        let selection = evaluate("1", 3).unwrap();
+        assert!(!selection.may_select(2, &none));
+    }
+
+    #[test]
+    fn sub_test_count_is_only_needed_for_last_sub_tests() {
+        assert!(evaluate("1.$", 1).unwrap().needs_sub_test_count());
+        assert!(evaluate("!*.2-*.$", 1).unwrap().needs_sub_test_count());
+        assert!(!evaluate("$,1.2-", 1).unwrap().needs_sub_test_count());
+    }
+
+    #[test]
+    fn within_a_test() {
+        let range = |expression| match evaluate(expression, 3).unwrap().include.remove(0) {
+            Selector::Range(range) => range,
+            selector => panic!("{selector:?} is not a range"),
+        };
+
+        assert!(range("2.3").is_within(2, 3));
+        assert!(range("$.1-$.4").is_within(3, 3));
+        assert!(!range("2").is_within(2, 3));
+        assert!(!range("2.3").is_within(1, 3));
+        assert!(!range("1.3-2.1").is_within(1, 3));
+    }
+
+    #[test]
+    fn unexpected_characters() {
+        assert_eq!(
+            error_of("1,2#", 3),
+            (SelectorErrorKind::UnexpectedCharacter('#'), 4)
+        );
+        assert_eq!(
+            error_of("1bad", 3),
+            (SelectorErrorKind::UnexpectedToken("bad".to_string()), 2)
+        );
+        assert_eq!(
+            error_of("1 2", 3),
+            (SelectorErrorKind::UnexpectedToken("2".to_string()), 3)
+        );
+        assert_eq!(
+            error_of("@1", 3),
+            (SelectorErrorKind::UnexpectedToken("1".to_string()), 2)
+        );
+        assert_eq!(
+            error_of("1-2-3", 3),
+            (SelectorErrorKind::UnexpectedToken("-".to_string()), 4)
+        );
+        assert_eq!(
+            error_of("2,@_slow", 3),
+            (SelectorErrorKind::UnexpectedCharacter('_'), 4)
+        );
+    }
+
+    #[test]
+    fn unexpected_end() {
+        assert_eq!(error_of("", 3), (SelectorErrorKind::UnexpectedEnd, 1));
+        assert_eq!(error_of("1,", 3), (SelectorErrorKind::UnexpectedEnd, 3));
+        assert_eq!(error_of("1.", 3), (SelectorErrorKind::UnexpectedEnd, 3));
+        assert_eq!(error_of("@", 3), (SelectorErrorKind::UnexpectedEnd, 2));
+        assert_eq!(error_of("!", 3), (SelectorErrorKind::UnexpectedEnd, 2));
+        assert_eq!(error_of("1-3:", 3), (SelectorErrorKind::UnexpectedEnd, 5));
+    }
+
+    #[test]
+    fn invalid_numbers() {
+        assert_eq!(
+            error_of("1,99999999999999999999999", 3),
+            (
+                SelectorErrorKind::InvalidNumber("99999999999999999999999".to_string()),
+                3
+            )
+        );
+        assert_eq!(error_of("0", 3), (SelectorErrorKind::ZeroIndex, 1));
+        assert_eq!(error_of("2.0", 3), (SelectorErrorKind::ZeroIndex, 3));
+    }
+
+    #[test]
+    fn invalid_ranges() {
+        assert_eq!(error_of("1,-", 3), (SelectorErrorKind::EmptyRange, 3));
+        assert_eq!(error_of("3-2", 3), (SelectorErrorKind::ReversedRange, 1));
+        assert_eq!(
+            error_of("2.5-2.1", 3),
+            (SelectorErrorKind::ReversedRange, 1)
+        );
+        assert_eq!(error_of("1, $-2", 3), (SelectorErrorKind::ReversedRange, 4));
+        assert_eq!(
+            error_of("*.1-2.1", 3),
+            (SelectorErrorKind::MixedWildcard, 1)
+        );
+        assert_eq!(error_of("*.2-", 3), (SelectorErrorKind::MixedWildcard, 1));
+    }
+
+    #[test]
+    fn invalid_steps() {
+        assert_eq!(error_of("1-3:0", 3), (SelectorErrorKind::InvalidStep, 5));
+        assert_eq!(
+            error_of("1.2-3.1:2", 3),
+            (SelectorErrorKind::InvalidStep, 9)
+        );
+        assert_eq!(error_of("1.2-:2", 3), (SelectorErrorKind::InvalidStep, 6));
+    }
+
+    #[test]
+    fn missing_tests() {
+        assert_eq!(
+            error_of("1,4", 3),
+            (
+                SelectorErrorKind::MissingTest {
+                    index: 4,
+                    test_count: 3
+                },
+                3
+            )
+        );
+        assert_eq!(
+            error_of("2-7.1", 3),
+            (
+                SelectorErrorKind::MissingTest {
+                    index: 7,
+                    test_count: 3
+                },
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn error_points_at_column() {
+        let error = evaluate("1, 2x", 3).unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Unexpected \"x\" at column 5.\n  1, 2x\n      ^"
+        );
+    }
+
+    #[test]
+    fn ranges_display_as_written() {
+        let selection = evaluate("1.2-3,-2,4-:2,*.1,$", 4).unwrap();
+        let displayed: Vec<String> = selection.ranges().map(ToString::to_string).collect();
+
+        assert_eq!(displayed, vec!["1.2-3", "-2", "4-:2", "*.1", "$"]);
+    }
+
+    #[test]
+    fn labels_are_validated() {
+        assert!(parse_label("max-n").is_ok());
+        assert!(parse_label("big_2").is_ok());
+        assert!(parse_label("_big2").is_err());
+        assert!(parse_label("2big").is_err());
+        assert!(parse_label("a b").is_err());
+        assert!(parse_label("").is_err());
+    }
 }
@@ -1,8 +1,100 @@
 use std::collections::HashMap;
 use std::error::Error;
+use std::fmt::{self, Display};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Read};
-use std::path::Path;
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::cache_file::{RunArgs, TestLabels};
+use crate::section_file::{is_section_file, SectionFileIterator};
+use crate::selector_evaluator::parse_label;
+
+/// Bytes of a line kept on each side of an error, so huge tests on a single
+/// line don't flood the terminal.
+const EXCERPT_RADIUS: usize = 40;
+
+/// A position in a test file, along with the part of its line around it.
+#[derive(Debug, Clone)]
+pub struct Location {
+    /// Starting at 1.
+    pub line: usize,
+    /// Byte column, starting at 1.
+    pub column: usize,
+    excerpt: Vec<u8>,
+    /// Offset of the position in `excerpt`.
+    caret: usize,
+    cut_start: bool,
+    cut_end: bool,
+}
+
+impl Location {
+    /// `offset` is the byte offset of the position in `line`, which is
+    /// given without its line ending.
+    pub fn new(line_number: usize, line: &[u8], offset: usize) -> Self {
+        let offset = offset.min(line.len());
+        let start = offset.saturating_sub(EXCERPT_RADIUS);
+        let end = line.len().min(offset + EXCERPT_RADIUS);
+
+        Self {
+            line: line_number,
+            column: offset + 1,
+            excerpt: line[start..end].to_vec(),
+            caret: offset - start,
+            cut_start: start > 0,
+            cut_end: end < line.len(),
+        }
+    }
+}
+
+/// A test file that doesn't read as tests, located down to the column.
+#[derive(Debug)]
+pub struct ParseError {
+    pub path: PathBuf,
+    pub location: Location,
+    pub message: String,
+}
+
+impl ParseError {
+    pub fn new(path: &Path, location: Location, message: impl Into<String>) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            location,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for ParseError {
+    /// Shown the way compilers show errors, with a caret under the position.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Location {
+            line,
+            column,
+            excerpt,
+            caret,
+            cut_start,
+            cut_end,
+        } = &self.location;
+
+        // Tabs are shown as single spaces so the caret stays aligned.
+        let shown = |bytes: &[u8]| String::from_utf8_lossy(bytes).replace('\t', " ");
+        let ellipsis = if *cut_start { "..." } else { "" };
+        let padding = " ".repeat(ellipsis.len() + shown(&excerpt[..*caret]).chars().count());
+        let gutter = " ".repeat(line.to_string().len());
+
+        write!(
+            f,
+            "{}:{line}:{column}: {}\n{gutter} |\n{line} | {ellipsis}{}{}\n{gutter} | {padding}^",
+            self.path.display(),
+            self.message,
+            shown(excerpt),
+            if *cut_end { "..." } else { "" },
+        )
+    }
+}
+
+impl Error for ParseError {}
 
 enum ModifierState {
     Enable,
@@ -10,24 +102,330 @@ enum ModifierState {
     Same,
 }
 
-#[derive(Default, Clone)]
-pub struct SimpleTest {
-    pub input: String,
-    pub expected_output: String,
+/// Input of a test, also used for expected outputs. Blocks and sections
+/// stay in their test file and are read as the run consumes them, so large
+/// ones are never held in memory.
+#[derive(Clone)]
+pub enum TestInput {
+    /// Shared so retrying after a recompilation doesn't copy the input.
+    Bytes(Arc<[u8]>),
+    /// `length` bytes of `path` from `offset`, read as `encoding` says.
+    File {
+        path: Arc<Path>,
+        offset: u64,
+        length: u64,
+        encoding: Encoding,
+    },
 }
 
-impl SimpleTest {
-    pub fn new() -> Self {
+/// How the span of a `TestInput::File` turns into the input.
+#[derive(Clone, Copy)]
+pub enum Encoding {
+    /// The inside of a block, read with `#trim` as it was when the block
+    /// closed and `#explicit-newline` as it was when it opened.
+    Block { trim: bool, explicit_newline: bool },
+    /// Section data without escaped lines, taken as it is.
+    Verbatim,
+}
+
+impl Default for TestInput {
+    fn default() -> Self {
+        TestInput::Bytes(Arc::default())
+    }
+}
+
+impl From<Vec<u8>> for TestInput {
+    fn from(bytes: Vec<u8>) -> Self {
+        TestInput::Bytes(Arc::from(bytes))
+    }
+}
+
+impl TestInput {
+    /// Reads the input from its start, streaming it from its file if it has one.
+    pub fn reader(&self) -> io::Result<Box<dyn Read + Send>> {
+        match self {
+            TestInput::Bytes(bytes) => Ok(Box::new(Cursor::new(bytes.clone()))),
+            TestInput::File {
+                path,
+                offset,
+                length,
+                encoding,
+            } => {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(*offset))?;
+                let span = BufReader::new(file).take(*length);
+
+                match *encoding {
+                    Encoding::Block {
+                        trim,
+                        explicit_newline,
+                    } => Ok(Box::new(BlockReader::new(span, trim, explicit_newline))),
+                    Encoding::Verbatim => Ok(Box::new(span)),
+                }
+            }
+        }
+    }
+
+    /// At most the first `limit` bytes of the input, to show it without
+    /// reading all of it.
+    pub fn head(&self, limit: usize) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.reader()?.take(limit as u64).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Reads the bytes inside a block, turning `\n` into a line break and
+/// dropping nested braces and the `-` of arrows. Shared by the parser, which
+/// only checks whether blocks are empty, and by `BlockReader`.
+#[derive(Default)]
+struct BlockDecoder {
+    /// A `-` or `\` standing for what the byte after it decides.
+    pending: Option<u8>,
+}
+
+impl BlockDecoder {
+    fn feed(&mut self, byte: u8) -> impl Iterator<Item = u8> {
+        let pending = match (self.pending.take(), byte) {
+            (Some(b'\\'), b'n') => return [Some(b'\n'), None].into_iter().flatten(),
+            (Some(b'-'), b'>') => None,
+            (pending, _) => pending,
+        };
+        let byte = match byte {
+            b'{' | b'}' => None,
+            b'-' | b'\\' => {
+                self.pending = Some(byte);
+                None
+            }
+            _ => Some(byte),
+        };
+
+        [pending, byte].into_iter().flatten()
+    }
+
+    /// Line breaks are part of the block unless `#explicit-newline` is
+    /// enabled, a `\` ending the line is dropped.
+    fn end_line(&mut self, explicit_newline: bool) -> impl Iterator<Item = u8> {
+        let dash = self.pending.take().filter(|&pending| pending == b'-');
+        [dash, (!explicit_newline).then_some(b'\n')]
+            .into_iter()
+            .flatten()
+    }
+
+    /// Ends the block, right before its closing brace.
+    fn finish(&mut self) -> Option<u8> {
+        self.pending.take()
+    }
+}
+
+/// Trims every line and the whole content as it streams in. Whitespace is
+/// held back until something follows it.
+#[derive(Default)]
+struct LineTrimmer {
+    spaces: Vec<u8>,
+    newlines: usize,
+    line_started: bool,
+    started: bool,
+}
+
+impl LineTrimmer {
+    fn feed(&mut self, byte: u8, output: &mut Vec<u8>) {
+        if byte == b'\n' {
+            self.spaces.clear();
+            self.line_started = false;
+            if self.started {
+                self.newlines += 1;
+            }
+        } else if byte.is_ascii_whitespace() {
+            if self.line_started {
+                self.spaces.push(byte);
+            }
+        } else {
+            output.extend(std::iter::repeat_n(
+                b'\n',
+                std::mem::take(&mut self.newlines),
+            ));
+            output.append(&mut self.spaces);
+            output.push(byte);
+            self.line_started = true;
+            self.started = true;
+        }
+    }
+}
+
+/// Lines of a block span, read the way `TestFileIterator` reads them:
+/// directive lines are skipped and `#explicit-newline` ones still apply.
+struct BlockLines {
+    decoder: BlockDecoder,
+    trimmer: Option<LineTrimmer>,
+    explicit_newline: bool,
+    /// The span starts after the `{`, so its first line is never a directive.
+    line_start: bool,
+    /// Leading whitespace of the current line, until it turns out not to be
+    /// a directive.
+    indent: Vec<u8>,
+    /// The directive on the current line, from its `#`.
+    directive: Option<Vec<u8>>,
+    carriage_return: bool,
+}
+
+impl BlockLines {
+    fn feed(&mut self, byte: u8, output: &mut Vec<u8>) {
+        if std::mem::take(&mut self.carriage_return) {
+            if byte == b'\n' {
+                return self.end_line(output);
+            }
+            self.line_byte(b'\r', output);
+        }
+
+        match byte {
+            b'\r' => self.carriage_return = true,
+            b'\n' => self.end_line(output),
+            _ => self.line_byte(byte, output),
+        }
+    }
+
+    fn line_byte(&mut self, byte: u8, output: &mut Vec<u8>) {
+        if let Some(directive) = &mut self.directive {
+            directive.push(byte);
+        } else if !self.line_start {
+            self.decode(byte, output);
+        } else if byte.is_ascii_whitespace() {
+            self.indent.push(byte);
+        } else if byte == b'#' {
+            self.line_start = false;
+            self.indent.clear();
+            self.directive = Some(vec![byte]);
+        } else {
+            self.line_start = false;
+            self.flush_indent(output);
+            self.decode(byte, output);
+        }
+    }
+
+    fn end_line(&mut self, output: &mut Vec<u8>) {
+        if let Some(directive) = self.directive.take() {
+            let directive = String::from_utf8_lossy(&directive);
+            if let (modifier_state, "explicit-newline") = state_directive(&directive) {
+                modifier_state.apply(&mut self.explicit_newline);
+            }
+        } else {
+            self.flush_indent(output);
+            for byte in self.decoder.end_line(self.explicit_newline) {
+                self.emit(byte, output);
+            }
+        }
+        self.line_start = true;
+    }
+
+    fn finish(&mut self, output: &mut Vec<u8>) {
+        if std::mem::take(&mut self.carriage_return) {
+            self.line_byte(b'\r', output);
+        }
+        self.flush_indent(output);
+        if let Some(byte) = self.decoder.finish() {
+            self.emit(byte, output);
+        }
+    }
+
+    fn flush_indent(&mut self, output: &mut Vec<u8>) {
+        for byte in std::mem::take(&mut self.indent) {
+            self.decode(byte, output);
+        }
+    }
+
+    fn decode(&mut self, byte: u8, output: &mut Vec<u8>) {
+        for byte in self.decoder.feed(byte) {
+            self.emit(byte, output);
+        }
+    }
+
+    fn emit(&mut self, byte: u8, output: &mut Vec<u8>) {
+        match &mut self.trimmer {
+            Some(trimmer) => trimmer.feed(byte, output),
+            None => output.push(byte),
+        }
+    }
+}
+
+/// Streams a block from its span of the test file.
+struct BlockReader {
+    span: io::Take<BufReader<File>>,
+    lines: BlockLines,
+    decoded: Vec<u8>,
+    position: usize,
+    done: bool,
+}
+
+impl BlockReader {
+    fn new(span: io::Take<BufReader<File>>, trim: bool, explicit_newline: bool) -> Self {
         Self {
-            input: String::new(),
-            expected_output: String::new(),
+            span,
+            lines: BlockLines {
+                decoder: BlockDecoder::default(),
+                trimmer: trim.then(LineTrimmer::default),
+                explicit_newline,
+                line_start: false,
+                indent: Vec::new(),
+                directive: None,
+                carriage_return: false,
+            },
+            decoded: Vec::new(),
+            position: 0,
+            done: false,
+        }
+    }
+}
+
+impl Read for BlockReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.decoded.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.decoded.clear();
+            self.position = 0;
+
+            let chunk = self.span.fill_buf()?;
+            let read = chunk.len();
+            if read == 0 {
+                self.lines.finish(&mut self.decoded);
+                self.done = true;
+            }
+            for &byte in chunk {
+                self.lines.feed(byte, &mut self.decoded);
+            }
+            self.span.consume(read);
         }
+
+        let read = buffer.len().min(self.decoded.len() - self.position);
+        buffer[..read].copy_from_slice(&self.decoded[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
     }
+}
 
-    pub fn with_capacity(capacity: usize) -> Self {
+/// Test data is kept as raw bytes, so binary or non-UTF-8 content survives
+/// until it is displayed.
+#[derive(Default, Clone)]
+pub struct SimpleTest {
+    pub input: TestInput,
+    /// None for tests only checking the program runs fine, given by blocks
+    /// of a `#standalone` file read on its own.
+    pub expected_output: Option<TestInput>,
+    /// Set by the `#args:` and `#env:` directives preceding the test.
+    pub run_args: RunArgs,
+    /// Set by the `#name:` and `#tag:` directives right before the test.
+    pub labels: TestLabels,
+}
+
+impl SimpleTest {
+    pub fn new() -> Self {
         Self {
-            input: String::with_capacity(capacity),
-            expected_output: String::with_capacity(capacity),
+            input: TestInput::default(),
+            expected_output: None,
+            run_args: RunArgs::default(),
+            labels: TestLabels::default(),
         }
     }
 }
@@ -40,15 +438,106 @@ impl ModifierState {
             _ => Self::Same,
         }
     }
+
+    fn apply(&self, state: &mut bool) {
+        match self {
+            ModifierState::Enable => *state = true,
+            ModifierState::Disable => *state = false,
+            ModifierState::Same => {}
+        }
+    }
+}
+
+/// The state a `#[enable:|disable:]<state>` directive line changes, and how.
+fn state_directive(line: &str) -> (ModifierState, &str) {
+    let full_state = &line[(line.find('#').unwrap_or_default() + 1)..];
+    full_state
+        .split_once(':')
+        .map(|(mod_state, state)| (ModifierState::from_str(mod_state), state.trim()))
+        .unwrap_or((ModifierState::Enable, full_state))
+}
+
+/// What the parser keeps of the block being read, its content being left in
+/// the file.
+#[derive(Default)]
+struct OpenBlock {
+    /// Where its content starts in the file, right after the `{`.
+    offset: u64,
+    explicit_newline: bool,
+    decoder: BlockDecoder,
+    /// Whether it holds any byte, and any besides whitespace.
+    any: bool,
+    content: bool,
+}
+
+impl OpenBlock {
+    fn record(&mut self, bytes: impl Iterator<Item = u8>) {
+        for byte in bytes {
+            self.any = true;
+            self.content |= !byte.is_ascii_whitespace();
+        }
+    }
+
+    fn feed(&mut self, byte: u8) {
+        let bytes = self.decoder.feed(byte);
+        self.record(bytes);
+    }
+
+    fn end_line(&mut self, explicit_newline: bool) {
+        let bytes = self.decoder.end_line(explicit_newline);
+        self.record(bytes);
+    }
+
+    /// Ends the block of `path` closing at `end`, returning its content and
+    /// whether it is empty.
+    fn close(mut self, path: &Arc<Path>, end: u64, trim: bool) -> (TestInput, bool) {
+        let last = self.decoder.finish();
+        self.record(last.into_iter());
+
+        let empty = match trim {
+            true => !self.content,
+            false => !self.any,
+        };
+        if empty {
+            return (TestInput::default(), true);
+        }
+
+        let content = TestInput::File {
+            path: path.clone(),
+            offset: self.offset,
+            length: end - self.offset,
+            encoding: Encoding::Block {
+                trim,
+                explicit_newline: self.explicit_newline,
+            },
+        };
+        (content, false)
+    }
 }
 
 pub struct TestFileIterator {
+    path: Arc<Path>,
     reader: BufReader<File>,
-    buffer: String,
+    line: Vec<u8>,
+    line_number: usize,
+    /// Where `line` starts in the file, and how much of the file was read.
+    line_offset: u64,
+    read_offset: u64,
+    /// Offset in `line` to continue from, after a test ending inside it.
+    resume: Option<usize>,
     stack: i32,
+    block: OpenBlock,
     pub test_buffer: SimpleTest,
+    /// Whether `test_buffer` holds a non-empty input waiting for its output.
+    has_input: bool,
     arrow_amount: usize,
+    /// Where the block being read opened, and where the pending input did.
+    block_start: Option<Location>,
+    input_start: Option<Location>,
     pub states: HashMap<&'static str, bool>,
+    run_args: RunArgs,
+    labels: TestLabels,
+    done: bool,
 }
 
 impl TestFileIterator {
@@ -57,193 +546,457 @@ impl TestFileIterator {
         let reader = BufReader::with_capacity(32 * 1024, file);
 
         Ok(Self {
+            path: Arc::from(test_file_path),
             reader,
-            buffer: String::with_capacity(4096), // Initial capacity for large buffer
+            line: Vec::new(),
+            line_number: 0,
+            line_offset: 0,
+            read_offset: 0,
+            resume: None,
             stack: 0,
-            test_buffer: SimpleTest::with_capacity(4096),
+            block: OpenBlock::default(),
+            test_buffer: SimpleTest::new(),
+            has_input: false,
             arrow_amount: 0,
+            block_start: None,
+            input_start: None,
             states: HashMap::from([
                 ("standalone", false),
                 ("trim", true),
                 ("explicit-newline", false),
             ]),
+            run_args: RunArgs::default(),
+            labels: TestLabels::default(),
+            done: false,
         })
     }
-}
 
-impl Iterator for TestFileIterator {
-    type Item = Result<SimpleTest, Box<dyn Error>>;
+    fn error(&self, location: Location, message: impl Into<String>) -> Box<dyn Error> {
+        Box::new(ParseError::new(&self.path, location, message))
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut ignore_next = false;
+    /// Error about the directive on the current line.
+    fn directive_error(&self, message: String) -> Box<dyn Error> {
+        let offset = self.line.iter().position(|&byte| byte == b'#');
+        self.error(
+            Location::new(self.line_number, &self.line, offset.unwrap_or_default()),
+            message,
+        )
+    }
 
-        for line in self.reader.by_ref().lines() {
-            let line = match line {
-                Ok(line) => line,
-                Err(e) => return Some(Err(Box::new(e))),
-            };
+    fn read_test(&mut self) -> Result<Option<SimpleTest>, Box<dyn Error>> {
+        loop {
+            // A line holding several blocks is read again after the one returned.
+            let start = self.resume.take();
+            if start.is_none() {
+                self.line.clear();
+                let read = self.reader.read_until(b'\n', &mut self.line)?;
+                if read == 0 {
+                    break;
+                }
+                self.line_number += 1;
+                self.line_offset = self.read_offset;
+                self.read_offset += read as u64;
 
-            if line.trim().starts_with('#') {
-                let full_state = &line[(line.find('#').unwrap_or_default() + 1)..];
-                let (modifier_state, state) = full_state
-                    .split_once(':')
-                    .map(|(mod_state, state)| (ModifierState::from_str(mod_state), state.trim()))
-                    .unwrap_or((ModifierState::Enable, full_state));
-
-                if let Some(state) = self.states.get_mut(state) {
-                    match modifier_state {
-                        ModifierState::Enable => *state = true,
-                        ModifierState::Disable => *state = false,
-                        _ => {}
+                // Strip the line ending the same way `BufRead::lines` does.
+                if self.line.ends_with(b"\n") {
+                    self.line.pop();
+                    if self.line.ends_with(b"\r") {
+                        self.line.pop();
                     }
                 }
-                continue;
-            }
 
-            let mut chars = line.chars().peekable();
-            let mut line_count = 0u32;
+                if self.line.trim_ascii_start().starts_with(b"#") {
+                    let line = String::from_utf8_lossy(&self.line);
+                    let full_state = &line[(line.find('#').unwrap_or_default() + 1)..];
+
+                    // `#args: <arguments>` and `#env: KEY=VALUE` apply to every
+                    // following test, an empty value resets them.
+                    match full_state.split_once(':') {
+                        Some((directive, value)) if directive.trim() == "args" => {
+                            match shell_words::split(value) {
+                                Ok(args) => self.run_args.args = args,
+                                Err(err) => {
+                                    return Err(self.directive_error(format!(
+                                        "Invalid #args directive: {err}"
+                                    )))
+                                }
+                            }
+                            continue;
+                        }
+                        Some((directive, value)) if directive.trim() == "env" => {
+                            let value = value.trim();
 
-            while let Some(chr) = chars.next() {
-                line_count += 1;
+                            if value.is_empty() {
+                                self.run_args.env.clear();
+                            } else if let Some((key, value)) = value.split_once('=') {
+                                self.run_args
+                                    .env
+                                    .insert(key.trim().to_string(), value.to_string());
+                            } else {
+                                return Err(self.directive_error(format!(
+                                    "Invalid #env directive \"{value}\", expected KEY=VALUE."
+                                )));
+                            }
+                            continue;
+                        }
+                        // `#name: <name>` and `#tag: <tags>` only label the next test.
+                        Some((directive, value)) if directive.trim() == "name" => {
+                            match parse_label(value.trim()) {
+                                Ok(name) => self.labels.name = Some(name),
+                                Err(err) => {
+                                    return Err(
+                                        self.directive_error(format!("Invalid #name: {err}"))
+                                    )
+                                }
+                            }
+                            continue;
+                        }
+                        Some((directive, value)) if directive.trim() == "tag" => {
+                            for tag in value.split([',', ' ']).filter(|tag| !tag.is_empty()) {
+                                match parse_label(tag) {
+                                    Ok(tag) => self.labels.tags.push(tag),
+                                    Err(err) => {
+                                        return Err(
+                                            self.directive_error(format!("Invalid #tag: {err}"))
+                                        )
+                                    }
+                                }
+                            }
+                            continue;
+                        }
+                        _ => {}
+                    }
 
-                if ignore_next {
-                    ignore_next = false;
+                    let (modifier_state, state) = state_directive(&line);
+                    if let Some(state) = self.states.get_mut(state) {
+                        modifier_state.apply(state);
+                    }
                     continue;
                 }
+            }
 
-                match chr {
-                    '{' => self.stack += 1,
-                    '-' => {
-                        if chars.peek() == Some(&'>') {
-                            self.arrow_amount += 1;
-                        } else if self.stack > 0 {
-                            self.buffer.push('-');
-                        }
-                    }
-                    '\\' => {
-                        if let Some(&next) = chars.peek() {
-                            if next == 'n' {
-                                self.buffer.push('\n');
-                                ignore_next = true;
-                            } else if self.stack > 0 {
-                                self.buffer.push('\\');
-                            }
+            let mut bytes = self
+                .line
+                .iter()
+                .copied()
+                .enumerate()
+                .skip(start.unwrap_or_default())
+                .peekable();
+
+            while let Some((offset, byte)) = bytes.next() {
+                match byte {
+                    b'{' => {
+                        if self.stack == 0 {
+                            self.block_start =
+                                Some(Location::new(self.line_number, &self.line, offset));
+                            self.block = OpenBlock {
+                                offset: self.line_offset + offset as u64 + 1,
+                                explicit_newline: self.states["explicit-newline"],
+                                ..OpenBlock::default()
+                            };
+                        } else {
+                            self.block.feed(byte);
                         }
+                        self.stack += 1;
                     }
-                    '}' => {
+                    b'}' => {
                         self.stack -= 1;
                         match self.stack.cmp(&0) {
                             std::cmp::Ordering::Less => {
-                                return Some(Err(format!(
-                                    "Bracket mismatch at line {}",
-                                    line_count
-                                )
-                                .into()))
+                                return Err(self.error(
+                                    Location::new(self.line_number, &self.line, offset),
+                                    "Unmatched '}', no block is open here.",
+                                ))
                             }
 
                             std::cmp::Ordering::Equal => {
-                                let mut inner_buffer = self.buffer.clone();
-
-                                if *self.states.get("trim").unwrap() {
-                                    inner_buffer = inner_buffer
-                                        .trim()
-                                        .lines()
-                                        .map(|line| line.trim())
-                                        .collect::<Vec<_>>()
-                                        .join("\n");
-                                }
+                                let (content, empty) = std::mem::take(&mut self.block).close(
+                                    &self.path,
+                                    self.line_offset + offset as u64,
+                                    self.states["trim"],
+                                );
 
-                                if self.test_buffer.input.is_empty() {
-                                    self.test_buffer.input = inner_buffer;
-                                    self.buffer.clear();
+                                if !self.has_input {
+                                    self.test_buffer.input = content;
+                                    self.test_buffer.run_args = self.run_args.clone();
+                                    self.test_buffer.labels = std::mem::take(&mut self.labels);
+                                    self.has_input = !empty;
                                     self.arrow_amount = 0;
+                                    self.input_start = self.block_start.take();
 
-                                    if *self.states.get("standalone").unwrap() {
+                                    if self.states["standalone"] {
                                         let test = std::mem::take(&mut self.test_buffer);
-                                        return Some(Ok(test));
+                                        self.has_input = false;
+                                        self.resume = Some(offset + 1);
+                                        return Ok(Some(test));
                                     }
-                                } else if self.test_buffer.expected_output.is_empty()
+                                } else if self.test_buffer.expected_output.is_none()
                                     && self.arrow_amount == 1
                                 {
-                                    self.test_buffer.expected_output = inner_buffer;
+                                    self.test_buffer.expected_output = Some(content);
                                     let test = std::mem::take(&mut self.test_buffer);
-                                    self.buffer.clear();
+                                    self.has_input = false;
                                     self.arrow_amount = 0;
-                                    return Some(Ok(test));
+                                    self.resume = Some(offset + 1);
+                                    return Ok(Some(test));
                                 } else if self.arrow_amount != 1 {
-                                    return Some(Err(
-                                        "Each input must be piped to output using '->'.".into(),
+                                    let location = self.block_start.take().unwrap_or_else(|| {
+                                        Location::new(self.line_number, &self.line, offset)
+                                    });
+                                    return Err(self.error(
+                                        location,
+                                        match self.arrow_amount {
+                                            0 => "Expected '->' between the input and this expected output.".to_string(),
+                                            amount => format!("Expected a single '->' before this expected output, found {amount}."),
+                                        },
                                     ));
                                 }
                             }
-                            std::cmp::Ordering::Greater => {}
+                            std::cmp::Ordering::Greater => self.block.feed(byte),
                         }
                     }
                     _ => {
+                        if byte == b'-' && bytes.peek().is_some_and(|&(_, next)| next == b'>') {
+                            self.arrow_amount += 1;
+                        }
                         if self.stack > 0 {
-                            self.buffer.push(chr);
+                            self.block.feed(byte);
                         }
                     }
                 }
             }
 
-            if !self.states.get("explicit-newline").unwrap() {
-                self.buffer.push('\n');
+            // Line breaks between blocks aren't part of any.
+            if self.stack > 0 {
+                self.block.end_line(self.states["explicit-newline"]);
+            }
+        }
+
+        if self.stack > 0 {
+            if let Some(location) = self.block_start.take() {
+                return Err(self.error(location, "Unclosed '{', the file ends inside this block."));
+            }
+        }
+        if self.has_input {
+            if let Some(location) = self.input_start.take() {
+                return Err(self.error(
+                    location,
+                    "Expected '->' and an expected output after this input, or #standalone for input-only blocks.",
+                ));
             }
         }
 
-        None
+        Ok(None)
+    }
+}
+
+impl Iterator for TestFileIterator {
+    type Item = Result<SimpleTest, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let test = self.read_test().transpose();
+        // Blocks after a malformed one can't be told apart reliably.
+        self.done = !matches!(test, Some(Ok(_)));
+        test
+    }
+}
+
+/// Tests of a brace delimited file or of a section file.
+pub enum TestFileReader {
+    Braces(Box<TestFileIterator>),
+    Sections(SectionFileIterator),
+}
+
+impl TestFileReader {
+    pub fn path(&self) -> &Path {
+        match self {
+            TestFileReader::Braces(iterator) => &iterator.path,
+            TestFileReader::Sections(iterator) => iterator.path(),
+        }
     }
 }
 
-pub fn read_test_file(test_file_path: &Path) -> Result<TestFileIterator, Box<dyn Error>> {
-    TestFileIterator::new(test_file_path)
+impl Iterator for TestFileReader {
+    type Item = Result<SimpleTest, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            TestFileReader::Braces(iterator) => iterator.next(),
+            TestFileReader::Sections(iterator) => iterator.next(),
+        }
+    }
+}
+
+pub fn read_test_file(test_file_path: &Path) -> Result<TestFileReader, Box<dyn Error>> {
+    match is_section_file(test_file_path)? {
+        true => Ok(TestFileReader::Sections(SectionFileIterator::new(
+            test_file_path,
+        )?)),
+        false => Ok(TestFileReader::Braces(Box::new(TestFileIterator::new(
+            test_file_path,
+        )?))),
+    }
 }
 
 pub struct MergedTestFileTterator {
-    input_iterator: TestFileIterator,
-    output_iterator: TestFileIterator,
+    input_iterator: TestFileReader,
+    output_iterator: TestFileReader,
+    /// Pairs read so far.
+    read: usize,
 }
 
 impl MergedTestFileTterator {
     fn new(
-        input_test_file_iterator: TestFileIterator,
-        output_test_file_iterator: TestFileIterator,
+        input_test_file_iterator: TestFileReader,
+        output_test_file_iterator: TestFileReader,
     ) -> Result<MergedTestFileTterator, Box<dyn Error>> {
         Ok(MergedTestFileTterator {
             input_iterator: input_test_file_iterator,
             output_iterator: output_test_file_iterator,
+            read: 0,
         })
     }
+
+    /// Error about the file of `longer` holding more blocks than the other one.
+    fn count_mismatch(&self, longer: &TestFileReader, shorter: &TestFileReader) -> Box<dyn Error> {
+        format!(
+            "{:?} has more blocks than {:?}, which ends after {} of them.",
+            longer.path(),
+            shorter.path(),
+            self.read
+        )
+        .into()
+    }
 }
 
 impl Iterator for MergedTestFileTterator {
     type Item = Result<SimpleTest, Box<dyn Error>>;
     fn next(&mut self) -> Option<Self::Item> {
-        let input_result = self.input_iterator.next()?;
-        let output_result = self.output_iterator.next()?;
-
-        let (input, expected_output) = match (input_result, output_result) {
-            (Ok(input_test), Ok(output_test)) => (input_test.input, output_test.input),
-            (Err(err), _) => {
-                return Some(Err(err));
-            }
-            (_, Err(err)) => {
-                return Some(Err(err));
-            }
-        };
+        let (input_test, output_test) =
+            match (self.input_iterator.next(), self.output_iterator.next()) {
+                (None, None) => return None,
+                (Some(Err(err)), _) | (_, Some(Err(err))) => return Some(Err(err)),
+                (Some(Ok(input_test)), Some(Ok(output_test))) => (input_test, output_test),
+                (Some(Ok(_)), None) => {
+                    return Some(Err(
+                        self.count_mismatch(&self.input_iterator, &self.output_iterator)
+                    ))
+                }
+                (None, Some(Ok(_))) => {
+                    return Some(Err(
+                        self.count_mismatch(&self.output_iterator, &self.input_iterator)
+                    ))
+                }
+            };
+        self.read += 1;
 
         Some(Ok(SimpleTest {
-            input,
-            expected_output,
+            input: input_test.input,
+            expected_output: Some(output_test.input),
+            run_args: input_test.run_args,
+            labels: input_test.labels,
         }))
     }
 }
 
 pub fn merge_test_file(
-    input_test_file_iterator: TestFileIterator,
-    output_test_file_iterator: TestFileIterator,
+    input_test_file_iterator: TestFileReader,
+    output_test_file_iterator: TestFileReader,
 ) -> Result<MergedTestFileTterator, Box<dyn Error>> {
     MergedTestFileTterator::new(input_test_file_iterator, output_test_file_iterator)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// The first error reading `contents` as the test file `name`, without
+    /// the path it starts with.
+    fn parse_error(name: &str, contents: &str) -> String {
+        let path = std::env::temp_dir().join(format!("erunner-{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+
+        let error = read_test_file(&path)
+            .unwrap()
+            .find_map(Result::err)
+            .map(|err| err.to_string());
+        let _ = fs::remove_file(&path);
+
+        let error = error.expect("the test file should have an error");
+        error
+            .strip_prefix(&format!("{}:", path.display()))
+            .unwrap()
+            .to_string()
+    }
+
+    /// Inputs and expected outputs of the test file `name` holding `contents`,
+    /// read back from the file.
+    fn blocks(name: &str, contents: &str) -> Vec<(String, Option<String>)> {
+        let path = std::env::temp_dir().join(format!("erunner-{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+
+        let read = |data: &TestInput| String::from_utf8(data.head(usize::MAX).unwrap()).unwrap();
+        let blocks = read_test_file(&path)
+            .unwrap()
+            .map(|test| {
+                let test = test.unwrap();
+                (read(&test.input), test.expected_output.as_ref().map(read))
+            })
+            .collect();
+        let _ = fs::remove_file(&path);
+        blocks
+    }
+
+    #[test]
+    fn block_contents() {
+        assert_eq!(
+            blocks(
+                "blocks.txt",
+                "{ 1 2 \r\n  3\\n4 } -> {\n  5\n\n  6  \n}\n#disable: trim\n#standalone\n\
+                 { a{b}\\\n  c->\r\n  #enable: explicit-newline\n d\n}\n",
+            ),
+            [
+                ("1 2\n3\n4".to_string(), Some("5\n\n6".to_string())),
+                (" ab\n  c>\n d".to_string(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_arrow() {
+        assert_eq!(
+            parse_error("arrow.txt", "{ 1 2 }\n{ 3 }\n"),
+            "2:1: Expected '->' between the input and this expected output.\n  |\n2 | { 3 }\n  | ^"
+        );
+        assert_eq!(
+            parse_error("trailing.txt", "{ 1 } -> { 2 }\n\n  { 1 2 }\n"),
+            "3:3: Expected '->' and an expected output after this input, or #standalone for input-only blocks.\n  |\n3 |   { 1 2 }\n  |   ^"
+        );
+    }
+
+    #[test]
+    fn unterminated_block() {
+        assert_eq!(
+            parse_error("unclosed.txt", "{ 1 } -> { 2 } { 3\n4\n"),
+            "1:16: Unclosed '{', the file ends inside this block.\n  |\n1 | { 1 } -> { 2 } { 3\n  |                ^"
+        );
+    }
+
+    #[test]
+    fn bad_section_header() {
+        assert_eq!(
+            parse_error("unknown.tests", "=== input\n1\n=== outptu\n2\n"),
+            "3:5: Unknown section \"outptu\", expected input, output, args, env or limits.\n  |\n3 | === outptu\n  |     ^"
+        );
+        assert_eq!(
+            parse_error("spacing.tests", "=== input\n1\n===output\n"),
+            "3:4: Expected a space after \"===\".\n  |\n3 | ===output\n  |    ^"
+        );
+    }
+}
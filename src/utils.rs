@@ -1,7 +1,8 @@
 use ring::digest;
 use std::io::Read;
 use std::io::{self};
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
 
 // Define the macro in a module
 pub mod logging {
@@ -105,6 +106,53 @@ pub fn limited_string(content: &str, cols: usize, rows: usize, truncated: bool)
     }
 }
 
+/// Parses a byte size such as `4096`, `512K`, `64M` or `1G` (binary units).
+pub fn parse_size(size: &str) -> Result<usize, String> {
+    let size = size.trim();
+    let digits = size.trim_end_matches(|chr: char| chr.is_ascii_alphabetic());
+
+    let multiplier: usize = match size[digits.len()..].to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        unit => return Err(format!("Unknown size unit \"{unit}\".")),
+    };
+
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|amount| amount.checked_mul(multiplier))
+        .ok_or_else(|| format!("\"{size}\" is not a valid size."))
+}
+
+/// Parses a duration such as `500ms`, `2s` or `1.5` (seconds).
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let amount = duration.trim_end_matches(|chr: char| chr.is_ascii_alphabetic());
+
+    let seconds_per_unit = match &duration[amount.len()..] {
+        "" | "s" => 1.0,
+        "ms" => 1e-3,
+        unit => return Err(format!("Unknown duration unit \"{unit}\".")),
+    };
+
+    amount
+        .parse::<f64>()
+        .ok()
+        .and_then(|amount| Duration::try_from_secs_f64(amount * seconds_per_unit).ok())
+        .filter(|duration| !duration.is_zero())
+        .ok_or_else(|| format!("\"{duration}\" is not a valid duration."))
+}
+
+/// Parses an environment variable given as `KEY=VALUE`.
+pub fn parse_env_var(variable: &str) -> Result<(String, String), String> {
+    match variable.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("\"{variable}\" is not in the form KEY=VALUE.")),
+    }
+}
+
 pub fn append_extension(extension: &str, path: PathBuf) -> PathBuf {
     let mut os_str = path.into_os_string();
     os_str.push(".");
@@ -112,6 +160,9 @@ pub fn append_extension(extension: &str, path: PathBuf) -> PathBuf {
     os_str.into()
 }
 
+/// Bytes of a test read to show it, far more than a terminal shows of it.
+pub const SHOWN_BYTES: usize = 64 * 1024;
+
 pub fn padded_string(content: &str, cols: usize, rows: usize, single_line: bool) -> String {
     limited_string(content, cols - 2, rows, single_line)
         .lines()
@@ -119,3 +170,86 @@ pub fn padded_string(content: &str, cols: usize, rows: usize, single_line: bool)
         .collect::<Vec<String>>()
         .join("\n")
 }
+
+/// Computes `path` relative to `base`, walking up with `..` where needed.
+/// Returns `None` when the two paths share no common root (e.g. different drives).
+pub fn relative_path(path: &Path, base: &Path) -> Option<PathBuf> {
+    if path.is_absolute() != base.is_absolute() {
+        return None;
+    }
+
+    let mut path_components = path.components().peekable();
+    let mut base_components = base.components().peekable();
+
+    if path.is_absolute() && path_components.peek() != base_components.peek() {
+        return None;
+    }
+
+    while let (Some(path_component), Some(base_component)) =
+        (path_components.peek(), base_components.peek())
+    {
+        if path_component != base_component {
+            break;
+        }
+
+        path_components.next();
+        base_components.next();
+    }
+
+    let mut relative = PathBuf::new();
+
+    for base_component in base_components {
+        match base_component {
+            Component::Normal(_) => relative.push(Component::ParentDir),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+
+    relative.extend(path_components);
+    Some(relative)
+}
+
+// Paths below are Unix ones, absolute paths on Windows start with a drive.
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn relative(path: &str, base: &str) -> Option<PathBuf> {
+        relative_path(Path::new(path), Path::new(base))
+    }
+
+    #[test]
+    fn relative_path_inside_base() {
+        assert_eq!(
+            relative("/project/binary", "/project"),
+            Some("binary".into())
+        );
+        assert_eq!(
+            relative("/project/src/a.rs", "/project"),
+            Some("src/a.rs".into())
+        );
+        assert_eq!(relative("/project", "/project"), Some("".into()));
+        assert_eq!(relative("src/a.rs", "src"), Some("a.rs".into()));
+    }
+
+    #[test]
+    fn relative_path_outside_base() {
+        assert_eq!(
+            relative("/shared/bin", "/project"),
+            Some("../shared/bin".into())
+        );
+        assert_eq!(relative("/", "/project/src"), Some("../..".into()));
+        assert_eq!(
+            relative("/project/a", "/project/b/c"),
+            Some("../../a".into())
+        );
+    }
+
+    #[test]
+    fn relative_path_without_common_root() {
+        assert_eq!(relative("binary", "/project"), None);
+        assert_eq!(relative("/project/binary", "project"), None);
+        assert_eq!(relative("a", "../b"), None);
+    }
+}
@@ -0,0 +1,151 @@
+//! Runs of the erunner binary in throwaway projects, some of them moved or with their cache relinked.
+#![cfg(unix)]
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Command};
+
+/// A project initialized in its own temporary directory, removed on drop.
+struct Project {
+    root: PathBuf,
+}
+
+impl Project {
+    /// Shell scripts are "compiled" by copying them, so they run through
+    /// `$(BINARY)` like compiled languages do.
+    fn new(name: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("erunner-{}-{name}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("binary")).unwrap();
+
+        let project = Self {
+            root: fs::canonicalize(root).unwrap(),
+        };
+        project.erunner(&["init"], 0);
+
+        let cache_path = project.root.join("erunner_cache.json");
+        let mut config: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&cache_path).unwrap()).unwrap();
+        config["languages_config"]["sh"] = serde_json::json!({
+            "compile": "cp $(FILE) $(BINARY)",
+            "run": "sh $(BINARY)",
+        });
+        fs::write(&cache_path, config.to_string()).unwrap();
+
+        project
+    }
+
+    fn write(&self, path: &str, contents: &str) {
+        fs::write(self.root.join(path), contents).unwrap();
+    }
+
+    /// Runs erunner in the project and checks its exit code, returning what
+    /// it printed.
+    fn erunner(&self, args: &[&str], exit_code: i32) -> String {
+        let output = Command::new(env!("CARGO_BIN_EXE_easy_runner"))
+            .args(args)
+            .current_dir(&self.root)
+            .output()
+            .unwrap();
+
+        assert_eq!(
+            output.status.code(),
+            Some(exit_code),
+            "erunner {args:?} printed:\n{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr),
+        );
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    }
+
+    /// Relinks the project onto itself, which stores the binary directory
+    /// relative to it.
+    fn relink(&self) {
+        let root = self.root.to_str().unwrap();
+        self.erunner(&["cache", "relink", root, root], 0);
+
+        let config: serde_json::Value = serde_json::from_str(
+            &fs::read_to_string(self.root.join("erunner_cache.json")).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(config["binary_dir_path"], "binary");
+    }
+}
+
+impl Drop for Project {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+#[test]
+fn sandboxed_run_after_relink() {
+    let project = Project::new("sandbox");
+    project.write("sum.sh", "read a b\necho $((a + b))\n");
+    project.erunner(&["test", "sum.sh", "add", "1 2", "3"], 0);
+
+    project.relink();
+    project.erunner(&["sandbox", "enable"], 0);
+    project.erunner(&["test", "sum.sh", "run"], 0);
+}
+
+#[test]
+fn io_files_run_after_relink() {
+    let project = Project::new("io-files");
+    project.write(
+        "sum.sh",
+        "read a b < input.txt\necho $((a + b)) > output.txt\n",
+    );
+    project.erunner(&["test", "sum.sh", "add", "1 2", "3"], 0);
+    project.erunner(
+        &["test", "sum.sh", "io-files", "input.txt", "output.txt"],
+        0,
+    );
+
+    project.relink();
+    project.erunner(&["test", "sum.sh", "run"], 0);
+}
+
+#[test]
+fn builds_stay_fresh_after_moving_the_project() {
+    let project = Project::new("move");
+    project.write("hello.sh", "echo hello\n");
+    project.erunner(&["test", "hello.sh", "add", "", "hello"], 0);
+    project.erunner(&["test", "hello.sh", "run"], 0);
+    project.relink();
+
+    let moved = Project {
+        root: project
+            .root
+            .with_file_name(format!("erunner-{}-moved", process::id())),
+    };
+    fs::rename(&project.root, &moved.root).unwrap();
+
+    let status = moved.erunner(&["status"], 0);
+    assert!(
+        status.contains("fresh"),
+        "erunner status printed:\n{status}"
+    );
+}
+
+#[test]
+fn run_after_moving_the_project() {
+    let project = Project::new("init-move");
+    project.write("hello.sh", "echo hello\n");
+    project.erunner(&["test", "hello.sh", "add", "", "hello"], 0);
+
+    let moved = Project {
+        root: project
+            .root
+            .with_file_name(format!("erunner-{}-init-moved", process::id())),
+    };
+    fs::rename(&project.root, &moved.root).unwrap();
+    moved.erunner(&["test", "hello.sh", "run"], 0);
+}
+
+#[test]
+fn run_of_a_failing_program_exits_with_1() {
+    let project = Project::new("run-failure");
+    project.write("fail.sh", "exit 7\n");
+    project.erunner(&["run", "fail.sh"], 1);
+}